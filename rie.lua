@@ -52,7 +52,7 @@ show "Finding Paste Location..."
 local x, y = rom_location()
 
 show "Compiling..."
-os.execute('cargo run --release "'..rie..'" --clip')
+os.execute('cargo run --release -- build "'..rie..'" --clip')
 
 show "Pasting..."
 g.paste(x, y, "or")
@@ -0,0 +1,228 @@
+use crate::CLIErr;
+use rieasm::rie::{assembler::FillPolicy, machine::Tape};
+use std::{path::PathBuf, str::FromStr};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Command {
+    Help,
+    Build,
+    Check,
+    Run,
+    Disasm,
+    Fmt,
+    Listing,
+    ImportTm,
+    ImportMinsky,
+    ImportBf,
+    FlipIfElse,
+}
+
+impl Command {
+    pub const COMMANDS: [(&'static str, Self); 11] = [
+        ("help", Self::Help),
+        ("build", Self::Build),
+        ("check", Self::Check),
+        ("run", Self::Run),
+        ("disasm", Self::Disasm),
+        ("fmt", Self::Fmt),
+        ("listing", Self::Listing),
+        ("import-tm", Self::ImportTm),
+        ("import-minsky", Self::ImportMinsky),
+        ("import-bf", Self::ImportBf),
+        ("flipifelse", Self::FlipIfElse),
+    ];
+
+    /// Whether the command reads `.rie` source, rather than some other kind of file.
+    pub fn takes_rie(self) -> bool {
+        !matches!(
+            self,
+            Self::Disasm | Self::ImportTm | Self::ImportMinsky | Self::ImportBf | Self::FlipIfElse
+        )
+    }
+}
+
+impl FromStr for Command {
+    type Err = CLIErr;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Self::COMMANDS
+            .iter()
+            .find_map(|&(name, command)| (s == name).then_some(command))
+            .ok_or_else(|| CLIErr::UnknownCommand(s.to_owned()))
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Format {
+    #[default]
+    Rle,
+    Rie,
+    Json,
+    Bin,
+    Verilog,
+    C,
+}
+
+impl Format {
+    pub const FORMATS: [(&'static str, Self); 6] = [
+        ("rle", Self::Rle),
+        ("rie", Self::Rie),
+        ("json", Self::Json),
+        ("bin", Self::Bin),
+        ("verilog", Self::Verilog),
+        ("c", Self::C),
+    ];
+}
+
+impl FromStr for Format {
+    type Err = CLIErr;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Self::FORMATS
+            .iter()
+            .find_map(|&(name, format)| (s == name).then_some(format))
+            .ok_or_else(|| CLIErr::UnknownFormat(s.to_owned()))
+    }
+}
+
+/// Where a command reads its source from.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Input {
+    Stdin,
+    Path(String),
+}
+
+impl Input {
+    /// Programs saved with `--format json` end with this, and are loaded instead of assembled.
+    pub const JSON_EXTENSION: &'static str = ".json";
+
+    /// `-` means standard input. Anything else is a path,
+    /// which gets '.rie' appended if `extension` is given and the path doesn't already end with it or `.json`.
+    pub fn new(arg: String, extension: Option<&str>) -> Self {
+        if arg == "-" {
+            return Self::Stdin;
+        }
+        let mut path = arg;
+        if let Some(extension) = extension {
+            if !path.ends_with(extension) && !path.ends_with(Self::JSON_EXTENSION) {
+                path.push_str(extension);
+            }
+        }
+        Self::Path(path)
+    }
+
+    /// Whether this is a program saved as JSON, rather than source code.
+    pub fn is_json(&self) -> bool {
+        matches!(self, Self::Path(path) if path.ends_with(Self::JSON_EXTENSION))
+    }
+}
+
+/// Where a command writes its result to.
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub enum Output {
+    #[default]
+    Stdout,
+    Clipboard,
+    File(PathBuf),
+}
+
+#[derive(Debug, Clone)]
+pub struct Args {
+    pub command: Command,
+    pub input: Option<Input>,
+    pub output: Output,
+    pub format: Format,
+    pub include_dirs: Vec<PathBuf>,
+    pub max_steps: u64,
+    pub trace: bool,
+    pub optimize: bool,
+    pub fill: Option<FillPolicy>,
+    pub listing: Option<PathBuf>,
+    pub source_map: Option<PathBuf>,
+    /// What registers start with in `run`, by name or number.
+    pub tapes: Vec<(String, Tape)>,
+}
+
+impl Args {
+    pub const DEFAULT_MAX_STEPS: u64 = 1_000_000;
+
+    pub fn parse(mut args: impl Iterator<Item = String>) -> Result<Self, CLIErr> {
+        use CLIErr::*;
+
+        let command = match args.next() {
+            Some(arg) if arg == "-h" || arg == "--help" => Command::Help,
+            Some(arg) => arg.parse()?,
+            None => Command::Help,
+        };
+
+        let mut input = None;
+        let mut output = None;
+        let mut format = None;
+        let mut include_dirs = vec![];
+        let mut max_steps = Self::DEFAULT_MAX_STEPS;
+        let mut trace = false;
+        let mut optimize = false;
+        let mut fill = None;
+        let mut listing = None;
+        let mut source_map = None;
+        let mut tapes = vec![];
+
+        let mut set_output = |out: Output| {
+            if output.replace(out).is_some() {
+                Err(MultipleOutputs)
+            } else {
+                Ok(())
+            }
+        };
+
+        while let Some(arg) = args.next() {
+            let mut value = |flag: &str| args.next().ok_or_else(|| MissingValue(flag.to_owned()));
+            match arg.as_str() {
+                "-o" | "--output" => set_output(Output::File(value(&arg)?.into()))?,
+                "-c" | "--clip" => set_output(Output::Clipboard)?,
+                "-f" | "--format" => format = Some(value(&arg)?.parse()?),
+                "-I" | "--include-dir" => include_dirs.push(value(&arg)?.into()),
+                "--steps" => {
+                    let steps = value(&arg)?;
+                    max_steps = steps.parse().map_err(|_| BadValue(arg.clone(), steps))?;
+                }
+                "--trace" => trace = true,
+                "-O" | "--optimize" => optimize = true,
+                "--fill" => {
+                    let policy = value(&arg)?;
+                    fill = Some(policy.parse().map_err(|_| BadValue(arg.clone(), policy))?);
+                }
+                "--listing" => listing = Some(value(&arg)?.into()),
+                "--source-map" => source_map = Some(value(&arg)?.into()),
+                "--tape" => {
+                    let tape = value(&arg)?;
+                    let bad = || BadValue(arg.clone(), tape.clone());
+                    let (register, bits) = tape.split_once('=').ok_or_else(bad)?;
+                    tapes.push((register.to_owned(), bits.parse().map_err(|()| bad())?));
+                }
+                "-" => input = Some(Input::Stdin),
+                flag if flag.starts_with('-') => return Err(InvalidFlag(flag.to_owned())),
+                _ if input.is_some() => return Err(UnexpectedArg(arg)),
+                _ => {
+                    let extension = command.takes_rie().then_some(".rie");
+                    input = Some(Input::new(arg, extension));
+                }
+            }
+        }
+
+        Ok(Self {
+            command,
+            input,
+            output: output.unwrap_or_default(),
+            format: format.unwrap_or_default(),
+            include_dirs,
+            max_steps,
+            trace,
+            optimize,
+            fill,
+            listing,
+            source_map,
+            tapes,
+        })
+    }
+}
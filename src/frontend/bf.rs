@@ -0,0 +1,369 @@
+use super::RieSource;
+use thiserror::Error;
+
+/// The register holding the cells of the program.
+pub const MEM: &str = "mem";
+/// The register that `,` reads bytes from.
+pub const IN: &str = "in";
+/// The register that `.` writes bytes to.
+pub const OUT: &str = "out";
+
+/// How many bits every cell has.
+pub const CELL_BITS: usize = 8;
+
+#[derive(Error, Debug)]
+pub enum BfErr {
+    #[error("The `[` at line {0}, column {1} is never closed with a `]`.")]
+    UnclosedLoop(usize, usize),
+
+    #[error("The `]` at line {0}, column {1} doesn't close any `[`.")]
+    StrayClose(usize, usize),
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Op {
+    /// `+` or `-`, wrapping around.
+    Add(bool),
+    /// A run of `>` and `<`, by how many cells it moves in total.
+    Move(i64),
+    Output,
+    Input,
+    /// A loop, with the line and column of its `[`.
+    Loop(usize, usize, Vec<Op>),
+}
+
+/// A Brainfuck program, along with the input given to it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BfProgram {
+    pub ops: Vec<Op>,
+    /// Everything after the first `!` in the source, which `,` reads one byte at a time.
+    pub input: Vec<u8>,
+}
+
+impl BfProgram {
+    /// Reads a Brainfuck program. Anything other than the 8 commands is a comment,
+    /// except for a `!`, after which everything is input for the program.
+    pub fn parse(source: &str) -> Result<Self, BfErr> {
+        let (code, input) = source.split_once('!').unwrap_or((source, ""));
+
+        // the ops of every loop that's still open, with where it was opened
+        let mut stack = vec![(0, 0, vec![])];
+        for (i, line) in code.lines().enumerate() {
+            for (j, c) in line.chars().enumerate() {
+                let ops: &mut Vec<Op> = &mut stack.last_mut().unwrap().2;
+                let op = match c {
+                    '+' => Op::Add(true),
+                    '-' => Op::Add(false),
+                    '>' | '<' => {
+                        let step = if c == '>' { 1 } else { -1 };
+                        match ops.last_mut() {
+                            Some(Op::Move(cells)) => *cells += step,
+                            _ => ops.push(Op::Move(step)),
+                        }
+                        continue;
+                    }
+                    '.' => Op::Output,
+                    ',' => Op::Input,
+                    '[' => {
+                        stack.push((i + 1, j + 1, vec![]));
+                        continue;
+                    }
+                    ']' => {
+                        if stack.len() == 1 {
+                            return Err(BfErr::StrayClose(i + 1, j + 1));
+                        }
+                        let (line, column, body) = stack.pop().unwrap();
+                        Op::Loop(line, column, body)
+                    }
+                    _ => continue,
+                };
+                stack.last_mut().unwrap().2.push(op);
+            }
+        }
+        if let Some(&(line, column, _)) = stack.get(1) {
+            return Err(BfErr::UnclosedLoop(line, column));
+        }
+        let (_line, _column, ops) = stack.pop().unwrap();
+        Ok(Self {
+            ops,
+            input: input.as_bytes().to_vec(),
+        })
+    }
+
+    fn uses(ops: &[Op], target: &Op) -> bool {
+        ops.iter().any(|op| match op {
+            Op::Loop(_, _, body) => Self::uses(body, target),
+            op => op == target,
+        })
+    }
+
+    /// Writes a `.rie` program that runs the Brainfuck program.
+    ///
+    /// Cells are groups of [`CELL_BITS`] bits on the `mem` register, least significant first,
+    /// and the head stays on the first bit of the current cell.
+    /// `,` copies the next group from the `in` register, which starts out holding the input,
+    /// and reads 0 once the input runs out.
+    /// `.` copies the cell onto the end of the `out` register.
+    /// Moving left of the first cell isn't supported, since the register doesn't extend that way.
+    ///
+    /// Every loop is split into a routine that checks the cell, one for its body, and one for what comes after it.
+    pub fn to_rie(&self) -> String {
+        let mut registers = vec![MEM];
+        if Self::uses(&self.ops, &Op::Input) {
+            registers.push(IN);
+        }
+        if Self::uses(&self.ops, &Op::Output) {
+            registers.push(OUT);
+        }
+
+        let mut rie = RieSource::new();
+        rie.comment("generated by rieasm import-bf");
+        rie.comment(&format!(
+            "every cell is {CELL_BITS} bits on the `{MEM}` register, least significant first."
+        ));
+        if registers.contains(&IN) {
+            rie.comment(&format!(
+                "`,` reads from the `{IN}` register, which starts with the input."
+            ));
+        }
+        if registers.contains(&OUT) {
+            rie.comment(&format!("`.` writes to the `{OUT}` register."));
+        }
+        rie.blank();
+        let header = registers
+            .iter()
+            .map(|register| format!("\treg:{register}"))
+            .collect::<String>();
+        rie.line(&format!("state\targ\tgoto\tread{header}"));
+        rie.line("entry\tstart");
+        let mut out = rie.finish();
+
+        let mut compiler = Compiler {
+            routines: vec![],
+            loops: 0,
+        };
+        let mut start = RieSource::new();
+        let writes_input = registers.contains(&IN) && !self.input.is_empty();
+        if writes_input {
+            start.comment(&format!("the program, after writing its input onto `{IN}`"));
+        }
+        start.open("routine start");
+        if writes_input {
+            for byte in &self.input {
+                for bit in 0..CELL_BITS {
+                    if byte >> bit & 1 == 1 {
+                        start.line(&format!("%@{IN}"));
+                    }
+                    start.line(&format!(">@{IN}"));
+                }
+            }
+            moves(&mut start, IN, -((self.input.len() * CELL_BITS) as i64));
+        }
+        compiler.block(start, &self.ops, None);
+        for routine in compiler.routines {
+            out.push('\n');
+            out.push_str(&routine);
+        }
+        out
+    }
+}
+
+/// Moves the head of a register by some number of cells.
+fn moves(rie: &mut RieSource, register: &str, cells: i64) {
+    if cells != 0 {
+        let cmd = if cells > 0 { ">" } else { "<" };
+        let cmds = cmd.repeat(cells.unsigned_abs() as usize);
+        rie.line(&format!("{cmds}@{register}"));
+    }
+}
+
+/// Turns Brainfuck into routines.
+struct Compiler {
+    routines: Vec<String>,
+    loops: usize,
+}
+
+impl Compiler {
+    /// Finishes a routine that has been opened, with `ops` and then going to `exit`.
+    fn block(&mut self, mut rie: RieSource, ops: &[Op], exit: Option<String>) {
+        for op in ops {
+            match op {
+                &Op::Add(up) => add(&mut rie, 0, up),
+                &Op::Move(cells) => moves(&mut rie, MEM, cells * CELL_BITS as i64),
+                Op::Output => output(&mut rie),
+                Op::Input => input(&mut rie),
+                Op::Loop(line, column, body) => {
+                    let i = self.loops;
+                    self.loops += 1;
+                    rie.line(&format!("goto loop{i}"));
+                    rie.close();
+                    self.routines.push(rie.finish());
+
+                    let mut check = RieSource::new();
+                    check.comment(&format!("the loop at line {line}, column {column}"));
+                    check.open(&format!("routine loop{i}"));
+                    is_zero(&mut check, 0, &format!("body{i}"), &format!("end{i}"));
+                    check.close();
+                    self.routines.push(check.finish());
+
+                    let mut inner = RieSource::new();
+                    inner.open(&format!("routine body{i}"));
+                    self.block(inner, body, Some(format!("loop{i}")));
+
+                    rie = RieSource::new();
+                    rie.open(&format!("routine end{i}"));
+                }
+            }
+        }
+        if let Some(exit) = exit {
+            rie.line(&format!("goto {exit}"));
+        }
+        rie.close();
+        self.routines.push(rie.finish());
+    }
+}
+
+/// Adds or subtracts 1 from the cell, with the head on `bit` after carrying past the bits before it.
+fn add(rie: &mut RieSource, bit: usize, up: bool) {
+    // adding carries past 1s and stops at a 0, and subtracting is the other way around
+    rie.open(&format!("if {MEM}?"));
+    if up {
+        carry(rie, bit, up);
+    } else {
+        stop(rie, bit);
+    }
+    rie.otherwise();
+    if up {
+        stop(rie, bit);
+    } else {
+        carry(rie, bit, up);
+    }
+    rie.close();
+}
+
+/// Flips the bit and goes on to the next one, or wraps around after the last.
+fn carry(rie: &mut RieSource, bit: usize, up: bool) {
+    if bit + 1 == CELL_BITS {
+        stop(rie, bit);
+    } else {
+        rie.line(&format!("%@{MEM} >@{MEM}"));
+        add(rie, bit + 1, up);
+    }
+}
+
+/// Flips the bit and goes back to the first bit of the cell.
+fn stop(rie: &mut RieSource, bit: usize) {
+    if bit == 0 {
+        rie.line(&format!("%@{MEM}"));
+    } else {
+        rie.line(&format!("%<@{MEM}"));
+        moves(rie, MEM, -(bit as i64 - 1));
+    }
+}
+
+/// Goes to `nonzero` if any bit of the cell from `bit` on is set, or `zero` otherwise.
+fn is_zero(rie: &mut RieSource, bit: usize, nonzero: &str, zero: &str) {
+    if bit == 0 {
+        rie.open(&format!("if {MEM}?"));
+    } else {
+        rie.open(&format!("if >@{MEM} {MEM}?"));
+    }
+    moves(rie, MEM, -(bit as i64));
+    rie.line(&format!("goto {nonzero}"));
+    rie.otherwise();
+    if bit + 1 == CELL_BITS {
+        moves(rie, MEM, -(bit as i64));
+        rie.line(&format!("goto {zero}"));
+    } else {
+        is_zero(rie, bit + 1, nonzero, zero);
+    }
+    rie.close();
+}
+
+/// Copies the cell onto the end of the output.
+fn output(rie: &mut RieSource) {
+    for bit in 0..CELL_BITS {
+        rie.open(&format!("if {MEM}?"));
+        rie.line(&format!("%@{OUT}"));
+        rie.close();
+        if bit + 1 < CELL_BITS {
+            rie.line(&format!(">@{MEM} >@{OUT}"));
+        }
+    }
+    let back = "<".repeat(CELL_BITS - 1);
+    rie.line(&format!("{back}@{MEM} >@{OUT}"));
+}
+
+/// Replaces the cell with the next byte of input.
+fn input(rie: &mut RieSource) {
+    for bit in 0..CELL_BITS {
+        // clear the bit, then copy the input's
+        rie.open(&format!("if {MEM}?"));
+        rie.line(&format!("%@{MEM}"));
+        rie.close();
+        rie.open(&format!("if {IN}?"));
+        rie.line(&format!("%@{MEM}"));
+        rie.close();
+        if bit + 1 < CELL_BITS {
+            rie.line(&format!(">@{MEM} >@{IN}"));
+        }
+    }
+    let back = "<".repeat(CELL_BITS - 1);
+    rie.line(&format!("{back}@{MEM} >@{IN}"));
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::rie::{machine::run_to_halt, RieProgram};
+
+    /// Runs a Brainfuck program until it halts, and returns what it wrote to `out`.
+    fn run(source: &str) -> Vec<u8> {
+        let bf = BfProgram::parse(source).unwrap();
+        let (program, _warnings) = RieProgram::parse(&bf.to_rie()).unwrap();
+        let machine = run_to_halt(&program);
+
+        let out = &machine.registers[program.register_names().find(OUT).unwrap()];
+        // the head of `out` ends up just after the last byte
+        let (_low, high) = out.bounds();
+        (0..high / CELL_BITS as i64)
+            .map(|byte| {
+                (0..CELL_BITS as i64)
+                    .map(|bit| (out.get(byte * CELL_BITS as i64 + bit) as u8) << bit)
+                    .sum()
+            })
+            .collect()
+    }
+
+    #[test]
+    fn outputs_a() {
+        assert_eq!(run(&format!("{}.", "+".repeat(65))), b"A");
+    }
+
+    #[test]
+    fn runs_loops() {
+        assert_eq!(run("++++++++[>++++++++<-]>+.>+++[<.>-]"), b"AAAA");
+    }
+
+    #[test]
+    fn wraps_around() {
+        assert_eq!(run("-.+."), [255, 0]);
+    }
+
+    #[test]
+    fn echoes_input() {
+        assert_eq!(run(",[.,]!hi"), b"hi");
+    }
+
+    #[test]
+    fn rejects_unmatched_brackets() {
+        assert!(matches!(
+            BfProgram::parse("+\n+]"),
+            Err(BfErr::StrayClose(2, 2))
+        ));
+        assert!(matches!(
+            BfProgram::parse("[[]"),
+            Err(BfErr::UnclosedLoop(1, 1))
+        ));
+    }
+}
@@ -0,0 +1,297 @@
+use crate::rie::header::RegisterNames;
+use std::collections::HashMap;
+use thiserror::Error;
+
+/// The register of `golly/FlipIfElse.rie` that holds the program.
+pub const PROG: &str = "prog";
+/// The register of `golly/FlipIfElse.rie` that needs a single set cell under the head before it starts.
+pub const TEMP: &str = "temp";
+
+#[derive(Error, Debug)]
+pub enum FieErr {
+    #[error("The FlipIfElse program has no instructions.")]
+    Empty,
+
+    #[error(
+        "Line {0}: {1:?} can't be the name of a label.\n\
+        Names must start with a letter or `_`, followed by letters, digits or `_`."
+    )]
+    BadName(usize, String),
+
+    #[error("Line {0}: The label {1:?} is already used on line {2}.")]
+    DuplicateLabel(usize, String, usize),
+
+    #[error("Line {0}: There is no label named {1:?}.")]
+    UnknownLabel(usize, String),
+
+    #[error(
+        "Line {0}: {1:?} is not an offset.\n\
+        Offsets are a number of cells to move `mem` by, like `0`, `+2` or `-1`."
+    )]
+    BadOffset(usize, String),
+
+    #[error(
+        "Line {0}: {1:?} is not a jump target.\n\
+        Targets are a label, `next`, or a number of instructions from this one, like `+2` or `-1`."
+    )]
+    BadTarget(usize, String),
+
+    #[error(
+        "Line {0}: {1:?} is not an instruction.\n\
+        Instructions look like `flip <offset> if <target> else <target>`, where `if` and `else` may be left out.\n\
+        Example: loop: flip +1 if loop else next"
+    )]
+    BadInstruction(usize, String),
+
+    #[error(
+        "Line {0}: This jumps to instruction {1}, but the program has {2} instructions, counting from 0.\n\
+        The furthest a jump can go is the end of the program, which can have a label after the last instruction."
+    )]
+    OutOfRange(usize, i64, usize),
+}
+
+/// One instruction, which moves the head of `mem`, flips the cell under it,
+/// then jumps depending on what the cell was flipped to.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Instr {
+    /// How many cells to move `mem` by before flipping.
+    pub offset: i64,
+    /// The instruction to go to if the cell is now 1.
+    pub if_target: usize,
+    /// The instruction to go to if the cell is now 0.
+    pub else_target: usize,
+}
+
+/// A program for the interpreter in `golly/FlipIfElse.rie`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FlipIfElse {
+    pub instrs: Vec<Instr>,
+}
+
+/// Removes a `#` or `--` comment from a line.
+fn strip_comment(line: &str) -> &str {
+    let end = ["#", "--"]
+        .iter()
+        .filter_map(|marker| line.find(marker))
+        .min()
+        .unwrap_or(line.len());
+    &line[..end]
+}
+
+/// Reads a signed number like `0`, `3`, `+3` or `-3`.
+fn parse_signed(word: &str) -> Option<i64> {
+    let digits = word.strip_prefix(['+', '-']).unwrap_or(word);
+    if digits.is_empty() || !digits.chars().all(|c| c.is_ascii_digit()) {
+        return None;
+    }
+    word.strip_prefix('+').unwrap_or(word).parse().ok()
+}
+
+/// Where an instruction jumps to, before labels are known.
+enum Target {
+    Label(String),
+    Relative(i64),
+}
+
+impl FlipIfElse {
+    /// Reads a program with one instruction per line, like `flip +1 if loop else next`.
+    ///
+    /// The offset is how many cells to move `mem` by, and the targets are a label,
+    /// `next`, or a number of instructions from this one like `+2` or `-1`.
+    /// A missing `if` or `else` goes to the next instruction.
+    /// Any line may start with labels like `loop:`, including after the last instruction,
+    /// and comments start with `#` or `--`.
+    pub fn parse(source: &str) -> Result<Self, FieErr> {
+        use FieErr::*;
+
+        let mut labels: HashMap<String, (usize, usize)> = HashMap::new();
+        // the line, offset and targets of every instruction, to resolve once every label is known
+        let mut parsed = vec![];
+
+        for (i, line) in source.lines().enumerate() {
+            let i = i + 1;
+            let mut words = strip_comment(line).split_whitespace().peekable();
+
+            while let Some(label) = words.peek().and_then(|word| word.strip_suffix(':')) {
+                if !RegisterNames::is_name(label) {
+                    return Err(BadName(i, label.to_owned()));
+                }
+                if let Some(&(_instr, line)) = labels.get(label) {
+                    return Err(DuplicateLabel(i, label.to_owned(), line));
+                }
+                labels.insert(label.to_owned(), (parsed.len(), i));
+                words.next();
+            }
+
+            let words = words.collect::<Vec<_>>();
+            let bad = || BadInstruction(i, words.join(" "));
+            let (offset, mut rest) = match words.as_slice() {
+                [] => continue,
+                ["flip", offset, rest @ ..] => (*offset, rest),
+                _ => return Err(bad()),
+            };
+            let offset = parse_signed(offset).ok_or_else(|| BadOffset(i, offset.to_owned()))?;
+
+            let mut targets = [None, None];
+            for (k, keyword) in ["if", "else"].into_iter().enumerate() {
+                if let [word, target, after @ ..] = rest {
+                    if *word == keyword {
+                        targets[k] = Some(match *target {
+                            "next" => Target::Relative(1),
+                            _ if target.starts_with(['+', '-']) => Target::Relative(
+                                parse_signed(target)
+                                    .ok_or_else(|| BadTarget(i, target.to_string()))?,
+                            ),
+                            _ if RegisterNames::is_name(target) => {
+                                Target::Label(target.to_string())
+                            }
+                            _ => return Err(BadTarget(i, target.to_string())),
+                        });
+                        rest = after;
+                    }
+                }
+            }
+            if !rest.is_empty() {
+                return Err(bad());
+            }
+            parsed.push((i, offset, targets));
+        }
+
+        if parsed.is_empty() {
+            return Err(Empty);
+        }
+        let len = parsed.len();
+        let instrs = parsed
+            .iter()
+            .enumerate()
+            .map(|(index, (i, offset, targets))| {
+                let resolve = |target: &Option<Target>| {
+                    let target = match target {
+                        None => index as i64 + 1,
+                        Some(Target::Relative(jump)) => index as i64 + jump,
+                        Some(Target::Label(label)) => match labels.get(label) {
+                            Some(&(target, _line)) => target as i64,
+                            None => return Err(UnknownLabel(*i, label.clone())),
+                        },
+                    };
+                    if !(0..=len as i64).contains(&target) {
+                        return Err(OutOfRange(*i, target, len));
+                    }
+                    Ok(target as usize)
+                };
+                Ok(Instr {
+                    offset: *offset,
+                    if_target: resolve(&targets[0])?,
+                    else_target: resolve(&targets[1])?,
+                })
+            })
+            .collect::<Result<_, _>>()?;
+        Ok(Self { instrs })
+    }
+
+    /// Writes the bits to start the `prog` register with, the way `run` shows registers,
+    /// so it can be passed as `--tape prog=<bits>`.
+    ///
+    /// Every number `n` is written as `n` zeros and then a 1, and every instruction is 3 numbers:
+    /// the offset, then the `if` and `else` jumps.
+    /// Offsets `0, +1, -1, +2, -2...` are the numbers `0, 1, 2, 3, 4...`,
+    /// and jumps are counted from the instruction after this one,
+    /// so jumps of `-1, +0, -2, +1, -3...` are the numbers `0, 1, 2, 3, 4...`.
+    /// The program is preceded by a single 1, so the interpreter can find the start of it when jumping back to instruction 0.
+    /// The head starts on the first bit after it.
+    pub fn prog_tape(&self) -> String {
+        let mut bits = String::from("1");
+        let mut number = |n: u64| {
+            bits.push_str(&"0".repeat(n as usize));
+            bits.push('1');
+        };
+        for (index, instr) in self.instrs.iter().enumerate() {
+            let offset = instr.offset;
+            number(if offset > 0 {
+                offset as u64 * 2 - 1
+            } else {
+                offset.unsigned_abs() * 2
+            });
+            for target in [instr.if_target, instr.else_target] {
+                let jump = target as i64 - (index as i64 + 1);
+                number(if jump >= 0 {
+                    jump as u64 * 2 + 1
+                } else {
+                    jump.unsigned_abs() * 2 - 2
+                });
+            }
+        }
+        let first = bits.remove(1);
+        format!("1[{first}]{}", &bits[1..])
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::rie::{machine::Machine, RieProgram};
+
+    /// Runs a program on the interpreter in `golly/FlipIfElse.rie` for a while,
+    /// since it never halts, and returns `mem`.
+    fn run(source: &str) -> String {
+        let fie = FlipIfElse::parse(source).unwrap();
+        let (program, _warnings) =
+            RieProgram::parse(include_str!("../../golly/FlipIfElse.rie")).unwrap();
+        let mut machine = Machine::new(&program);
+        let names = program.register_names();
+        for (register, bits) in [(PROG, fie.prog_tape()), (TEMP, "1".to_owned())] {
+            machine.registers[names.find(register).unwrap()] = bits.parse().unwrap();
+        }
+        machine.run(&program, 100_000);
+        machine.registers[names.find("mem").unwrap()].to_string()
+    }
+
+    #[test]
+    fn encodes_instructions() {
+        let fie = FlipIfElse::parse("flip +1\nflip +1\nflip -1 if end else end\nend:").unwrap();
+        assert_eq!(
+            fie.instrs[2],
+            Instr {
+                offset: -1,
+                if_target: 3,
+                else_target: 3,
+            }
+        );
+        // offsets +1 and -1 are 1 and 2, and jumps to the next instruction are 1
+        assert_eq!(fie.prog_tape(), "1[0]101010101010010101");
+    }
+
+    #[test]
+    fn runs_on_the_interpreter() {
+        assert_eq!(run("flip +1\nflip +1\nend:"), "01[1]");
+        // sets cells 1 and 0, then goes back to flip cell 1 again, which clears it
+        let source = "\
+top: flip +1 if next else end
+     flip -1 if top else end
+end:";
+        assert_eq!(run(source), "1[0]");
+    }
+
+    #[test]
+    fn rejects_bad_programs() {
+        use FieErr::*;
+        assert!(matches!(FlipIfElse::parse("# nothing"), Err(Empty)));
+        assert!(matches!(FlipIfElse::parse("flip x"), Err(BadOffset(1, _))));
+        assert!(matches!(
+            FlipIfElse::parse("flip 0 if nowhere"),
+            Err(UnknownLabel(1, _))
+        ));
+        assert!(matches!(
+            FlipIfElse::parse("a: flip 0\na: flip 0"),
+            Err(DuplicateLabel(2, _, 1))
+        ));
+        assert!(matches!(
+            FlipIfElse::parse("flip 0 else -2"),
+            Err(OutOfRange(1, -2, 1))
+        ));
+        assert!(matches!(
+            FlipIfElse::parse("flop 0"),
+            Err(BadInstruction(1, _))
+        ));
+    }
+}
@@ -0,0 +1,312 @@
+use super::RieSource;
+use crate::rie::header::RegisterNames;
+use std::collections::HashSet;
+use thiserror::Error;
+
+#[derive(Error, Debug)]
+pub enum MinskyErr {
+    #[error("The counter machine has no instructions.")]
+    Empty,
+
+    #[error(
+        "Line {0}: {1:?} can't be the name of a label or counter.\n\
+        Names must start with a letter or `_`, followed by letters, digits or `_`."
+    )]
+    BadName(usize, String),
+
+    #[error("Line {0}: The label {1:?} is already used on line {2}.")]
+    DuplicateLabel(usize, String, usize),
+
+    #[error("Line {0}: There is no label named {1:?}.")]
+    UnknownLabel(usize, String),
+
+    #[error(
+        "Line {0}: {1:?} is not an instruction.\n\
+        Instructions are `inc <counter>`, `jzdec <counter>, <label>`, and `halt`."
+    )]
+    UnknownInstruction(usize, String),
+
+    #[error(
+        "Line {0}: `{1}` takes {2}.\n\
+        Example: {3}"
+    )]
+    BadArgs(usize, &'static str, &'static str, &'static str),
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Instr {
+    /// Adds 1 to a counter.
+    Inc(usize),
+    /// Jumps to a label if a counter is 0, and subtracts 1 from it otherwise.
+    JzDec(usize, String),
+    Halt,
+}
+
+/// Instructions that run one after another, starting at a label.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Block {
+    /// Only the first block may be without a label.
+    pub label: Option<String>,
+    pub instrs: Vec<Instr>,
+}
+
+/// A Minsky counter machine: counters that start at 0, and instructions that change and test them.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CounterMachine {
+    /// The name of every counter, in the order they're first used.
+    pub counters: Vec<String>,
+    /// The program, split at every label. Running off the end of a block runs the next one.
+    pub blocks: Vec<Block>,
+}
+
+/// Removes a `#`, `;` or `--` comment from a line.
+fn strip_comment(line: &str) -> &str {
+    let end = ["#", ";", "--"]
+        .iter()
+        .filter_map(|marker| line.find(marker))
+        .min()
+        .unwrap_or(line.len());
+    &line[..end]
+}
+
+impl CounterMachine {
+    /// Reads a counter machine, with one instruction per line:
+    /// `inc <counter>`, `jzdec <counter>, <label>`, or `halt`.
+    /// Any line may start with labels like `loop:`, and running off the end of the program halts.
+    pub fn parse(source: &str) -> Result<Self, MinskyErr> {
+        use MinskyErr::*;
+
+        let mut counters: Vec<String> = vec![];
+        let mut blocks = vec![Block {
+            label: None,
+            instrs: vec![],
+        }];
+        let mut labels = HashSet::new();
+        let mut label_lines = vec![];
+        // every jump, with the line it's on, to check once every label is known
+        let mut jumps = vec![];
+
+        for (i, line) in source.lines().enumerate() {
+            let i = i + 1;
+            let mut words = strip_comment(line)
+                .split([' ', '\t', ','])
+                .filter(|word| !word.is_empty())
+                .peekable();
+
+            while let Some(label) = words.peek().and_then(|word| word.strip_suffix(':')) {
+                if !RegisterNames::is_name(label) {
+                    return Err(BadName(i, label.to_owned()));
+                }
+                if !labels.insert(label.to_owned()) {
+                    let (_label, line) = label_lines
+                        .iter()
+                        .find(|(other, _line)| other == label)
+                        .expect("Every label has a line.");
+                    return Err(DuplicateLabel(i, label.to_owned(), *line));
+                }
+                label_lines.push((label.to_owned(), i));
+                blocks.push(Block {
+                    label: Some(label.to_owned()),
+                    instrs: vec![],
+                });
+                words.next();
+            }
+
+            let mut counter = |name: &str| {
+                if !RegisterNames::is_name(name) {
+                    return Err(BadName(i, name.to_owned()));
+                }
+                Ok(match counters.iter().position(|counter| counter == name) {
+                    Some(counter) => counter,
+                    None => {
+                        counters.push(name.to_owned());
+                        counters.len() - 1
+                    }
+                })
+            };
+            let words = words.collect::<Vec<_>>();
+            let instr = match words.as_slice() {
+                [] => continue,
+                ["inc", name] => Instr::Inc(counter(name)?),
+                ["inc", ..] => return Err(BadArgs(i, "inc", "a counter", "inc a")),
+                ["jzdec", name, label] => {
+                    jumps.push((i, label.to_string()));
+                    Instr::JzDec(counter(name)?, label.to_string())
+                }
+                ["jzdec", ..] => {
+                    return Err(BadArgs(
+                        i,
+                        "jzdec",
+                        "a counter and a label",
+                        "jzdec a, done",
+                    ))
+                }
+                ["halt"] => Instr::Halt,
+                ["halt", ..] => return Err(BadArgs(i, "halt", "nothing", "halt")),
+                [word, ..] => return Err(UnknownInstruction(i, word.to_string())),
+            };
+            blocks.last_mut().unwrap().instrs.push(instr);
+        }
+
+        if let Some((i, label)) = jumps
+            .into_iter()
+            .find(|(_i, label)| !labels.contains(label))
+        {
+            return Err(UnknownLabel(i, label));
+        }
+        if blocks.iter().all(|block| block.instrs.is_empty()) {
+            return Err(Empty);
+        }
+        if blocks[0].instrs.is_empty() {
+            blocks.remove(0);
+        }
+        Ok(Self { counters, blocks })
+    }
+
+    /// Writes a `.rie` program that runs the machine, with a register for every counter.
+    ///
+    /// A counter holding `n` has its cells `1` to `n` set, with the head on cell `n`,
+    /// so `inc` is `>` then `%`, and `jzdec` reads the cell under the head, and clears it with `%<` if it was set.
+    /// Every block is a routine named after its label.
+    pub fn to_rie(&self) -> String {
+        // the first block doesn't need a label, so it gets one that no other block has
+        let mut start = String::from("start");
+        while self
+            .blocks
+            .iter()
+            .any(|block| block.label.as_ref() == Some(&start))
+        {
+            start.push('_');
+        }
+        let name = |block: &Block| block.label.clone().unwrap_or_else(|| start.clone());
+
+        let mut rie = RieSource::new();
+        rie.comment("generated by rieasm import-minsky");
+        rie.comment(&format!(
+            "a counter machine with {} counters, each on its own register.",
+            self.counters.len()
+        ));
+        rie.comment("a counter holding n has cells 1 to n set, with the head on cell n.");
+        rie.blank();
+        let registers = self
+            .counters
+            .iter()
+            .map(|counter| format!("\treg:{counter}"))
+            .collect::<String>();
+        rie.line(&format!("state\targ\tgoto\tread{registers}"));
+        rie.line(&format!("entry\t{}", name(&self.blocks[0])));
+
+        for (i, block) in self.blocks.iter().enumerate() {
+            rie.blank();
+            rie.open(&format!("routine {}", name(block)));
+            for instr in &block.instrs {
+                match instr {
+                    Instr::Inc(counter) => {
+                        let counter = &self.counters[*counter];
+                        rie.line(&format!(">@{counter} %@{counter}"));
+                    }
+                    Instr::JzDec(counter, label) => {
+                        let counter = &self.counters[*counter];
+                        rie.open(&format!("if {counter}?"));
+                        rie.line(&format!("%<@{counter}"));
+                        rie.otherwise();
+                        rie.line(&format!("goto {label}"));
+                        rie.close();
+                    }
+                    Instr::Halt => rie.line("halt"),
+                }
+            }
+            // running off the end of the last block halts, like reaching the end of a routine
+            let falls_through = block.instrs.last() != Some(&Instr::Halt);
+            if let Some(next) = self.blocks.get(i + 1).filter(|_| falls_through) {
+                rie.line(&format!("goto {}", name(next)));
+            }
+            rie.close();
+        }
+        rie.finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::rie::{machine::run_to_halt, RieProgram};
+
+    /// Multiplies `a` by `b` into `c`, using `t` to put `b` back, and `z` which is always 0 to jump.
+    const MULTIPLY: &str = "\
+inc a
+inc a
+inc a
+inc b
+inc b
+inc b
+inc b
+outer: jzdec a, done
+inner: jzdec b, restore  ; add b to c
+    inc c
+    inc t
+    jzdec z, inner
+restore: jzdec t, outer
+    inc b
+    jzdec z, restore
+done: halt
+";
+
+    /// Runs the machine until it halts, and returns the value of every counter.
+    fn run(source: &str) -> Vec<(String, usize)> {
+        let counters = CounterMachine::parse(source).unwrap();
+        let (program, _warnings) = RieProgram::parse(&counters.to_rie()).unwrap();
+        let machine = run_to_halt(&program);
+        counters
+            .counters
+            .into_iter()
+            .zip(&machine.registers)
+            .map(|(name, tape)| (name, tape.to_string().matches('1').count()))
+            .collect()
+    }
+
+    #[test]
+    fn multiplies() {
+        let counters = run(MULTIPLY);
+        let value = |name: &str| {
+            counters
+                .iter()
+                .find(|(counter, _)| counter == name)
+                .unwrap()
+                .1
+        };
+        assert_eq!(value("a"), 0);
+        assert_eq!(value("b"), 4);
+        assert_eq!(value("c"), 12);
+        assert_eq!(value("t"), 0);
+    }
+
+    #[test]
+    fn runs_off_the_end() {
+        assert_eq!(run("inc a\ninc a\n"), [("a".to_owned(), 2)]);
+    }
+
+    #[test]
+    fn rejects_bad_programs() {
+        assert!(matches!(
+            CounterMachine::parse("; nothing"),
+            Err(MinskyErr::Empty)
+        ));
+        assert!(matches!(
+            CounterMachine::parse("jzdec a, nowhere"),
+            Err(MinskyErr::UnknownLabel(1, _))
+        ));
+        assert!(matches!(
+            CounterMachine::parse("x: inc a\nx: halt"),
+            Err(MinskyErr::DuplicateLabel(2, _, 1))
+        ));
+        assert!(matches!(
+            CounterMachine::parse("inc a b"),
+            Err(MinskyErr::BadArgs(1, "inc", ..))
+        ));
+        assert!(matches!(
+            CounterMachine::parse("dec a"),
+            Err(MinskyErr::UnknownInstruction(1, _))
+        ));
+    }
+}
@@ -0,0 +1,67 @@
+//! Frontends that turn other kinds of programs into `.rie` source.
+
+pub mod bf;
+pub mod flipifelse;
+pub mod minsky;
+pub mod tm;
+
+/// `.rie` source being written by a frontend, one line at a time.
+/// Lines of code are indented by how many blocks they're in.
+#[derive(Debug, Clone, Default)]
+pub struct RieSource {
+    text: String,
+    depth: usize,
+}
+
+impl RieSource {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// A `--` comment, which isn't indented.
+    pub fn comment(&mut self, comment: &str) {
+        self.text.push_str("--");
+        if !comment.is_empty() {
+            self.text.push(' ');
+            self.text.push_str(comment);
+        }
+        self.text.push('\n');
+    }
+
+    pub fn blank(&mut self) {
+        self.text.push('\n');
+    }
+
+    /// A line of code, like a header, a directive, or a statement of a routine.
+    pub fn line(&mut self, code: &str) {
+        for _ in 0..=self.depth {
+            self.text.push('\t');
+        }
+        self.text.push_str(code);
+        self.text.push('\n');
+    }
+
+    /// A line that opens a block, like `routine main` or `if prog?`.
+    pub fn open(&mut self, code: &str) {
+        self.line(&format!("{code} {{"));
+        self.depth += 1;
+    }
+
+    /// Closes the innermost block.
+    pub fn close(&mut self) {
+        self.depth -= 1;
+        self.line("}");
+    }
+
+    /// Closes an `if` block and opens its `else` block.
+    pub fn otherwise(&mut self) {
+        self.depth -= 1;
+        self.line("} else {");
+        self.depth += 1;
+    }
+
+    pub fn finish(self) -> String {
+        debug_assert_eq!(self.depth, 0, "A block was never closed.");
+        self.text
+    }
+}
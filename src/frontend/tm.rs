@@ -0,0 +1,374 @@
+use super::RieSource;
+use std::fmt::Display;
+use thiserror::Error;
+
+/// The name of the register that holds the tape.
+pub const TAPE: &str = "tape";
+
+#[derive(Error, Debug)]
+pub enum TmErr {
+    #[error("The Turing machine has no transitions.")]
+    Empty,
+
+    #[error(
+        "Line {0} should have 5 parts: a state, the symbol it reads, the symbol it writes, a move, and the next state.\n\
+        Example: A 0 1 R B"
+    )]
+    BadTuple(usize),
+
+    #[error("{0:?} is not a symbol. Symbols must be 0 or 1, or _ for 0.")]
+    BadSymbol(String),
+
+    #[error("{0:?} is not a move. Moves must be L or R.")]
+    BadMove(String),
+
+    #[error("{0:?} can't be the name of a state. Names may only have letters, digits and `_`.")]
+    BadStateName(String),
+
+    #[error("State {0} reading {1} is given more than one transition.")]
+    Duplicate(String, u8),
+
+    #[error(
+        "{0:?} is not a transition in bbchallenge's format.\n\
+        Transitions look like `1RB`, or `---` for one that halts."
+    )]
+    BadTransition(String),
+
+    #[error(
+        "State {0} has {1:?}, but every state needs 2 transitions, like `1RB0LC`.\n\
+        States are separated by `_`, as in 1RB1LB_1LA1RZ"
+    )]
+    BadStateTransitions(char, String),
+
+    #[error("bbchallenge's format only has room for 26 states, but there are {0}.")]
+    TooManyStates(usize),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Move {
+    Left,
+    Right,
+}
+
+impl Move {
+    fn parse(s: &str) -> Result<Self, TmErr> {
+        match s {
+            "L" | "l" => Ok(Self::Left),
+            "R" | "r" => Ok(Self::Right),
+            _ => Err(TmErr::BadMove(s.to_owned())),
+        }
+    }
+}
+
+impl Display for Move {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Left => write!(f, "L"),
+            Self::Right => write!(f, "R"),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Transition {
+    pub write: bool,
+    pub shift: Move,
+    /// The state to go to, which halts if it has no transitions.
+    pub next: String,
+}
+
+/// A Turing machine with the symbols 0 and 1, where 0 is blank.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TuringMachine {
+    /// The name and transitions for 0 and 1 of every state, starting with the first one.
+    /// Missing transitions halt.
+    pub states: Vec<(String, [Option<Transition>; 2])>,
+}
+
+fn parse_symbol(s: &str) -> Result<bool, TmErr> {
+    match s {
+        "0" | "_" => Ok(false),
+        "1" => Ok(true),
+        _ => Err(TmErr::BadSymbol(s.to_owned())),
+    }
+}
+
+fn check_state_name(name: &str) -> Result<(), TmErr> {
+    let valid = !name.is_empty() && name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_');
+    valid
+        .then_some(())
+        .ok_or_else(|| TmErr::BadStateName(name.to_owned()))
+}
+
+/// Whether a line of a Turing machine file is a comment.
+fn is_comment(line: &str) -> bool {
+    ["#", "//", "--", ";"]
+        .iter()
+        .any(|prefix| line.starts_with(prefix))
+}
+
+impl TuringMachine {
+    /// Reads a machine in either format, telling them apart by whether it's a single word.
+    ///
+    /// - bbchallenge's format is a single word like `1RB1LC_1RC1RB_1RD0LE_1LA1LD_1RZ0LA`,
+    ///   with the transitions for 0 and 1 of states `A`, `B`, `C`... separated by `_`.
+    /// - The 5-tuple format has a line like `A 0 1 R B` for every transition,
+    ///   with the state, the symbol it reads, the symbol it writes, the move, and the next state.
+    ///   Parts may also be separated by commas, and the machine starts at the first state.
+    ///
+    /// Lines starting with `#`, `//`, `--` or `;` are comments.
+    pub fn parse(source: &str) -> Result<Self, TmErr> {
+        let lines = source
+            .lines()
+            .enumerate()
+            .map(|(i, line)| (i + 1, line.trim()))
+            .filter(|(_i, line)| !line.is_empty() && !is_comment(line))
+            .collect::<Vec<_>>();
+        match lines.as_slice() {
+            [] => Err(TmErr::Empty),
+            [(_i, word)] if !word.contains([' ', '\t', ',']) => Self::parse_bbchallenge(word),
+            _ => Self::parse_tuples(&lines),
+        }
+    }
+
+    fn parse_bbchallenge(word: &str) -> Result<Self, TmErr> {
+        use TmErr::*;
+
+        let groups = word.split('_').collect::<Vec<_>>();
+        if groups.len() > 26 {
+            return Err(TooManyStates(groups.len()));
+        }
+        let name = |i: usize| char::from(b'A' + i as u8);
+        let mut states = vec![];
+        for (i, group) in groups.iter().enumerate() {
+            let chars = group.chars().collect::<Vec<_>>();
+            if chars.len() != 6 {
+                return Err(BadStateTransitions(name(i), group.to_string()));
+            }
+            let mut transitions = [None, None];
+            for (transition, chunk) in transitions.iter_mut().zip(chars.chunks(3)) {
+                let text = chunk.iter().collect::<String>();
+                if text == "---" {
+                    continue;
+                }
+                let bad = || BadTransition(text.clone());
+                let write = parse_symbol(&chunk[0].to_string()).map_err(|_| bad())?;
+                let shift = Move::parse(&chunk[1].to_string()).map_err(|_| bad())?;
+                if !chunk[2].is_ascii_alphabetic() {
+                    return Err(bad());
+                }
+                *transition = Some(Transition {
+                    write,
+                    shift,
+                    // letters past the last state, like `Z`, halt
+                    next: chunk[2].to_ascii_uppercase().to_string(),
+                });
+            }
+            states.push((name(i).to_string(), transitions));
+        }
+        Ok(Self { states })
+    }
+
+    fn parse_tuples(lines: &[(usize, &str)]) -> Result<Self, TmErr> {
+        use TmErr::*;
+
+        let mut states: Vec<(String, [Option<Transition>; 2])> = vec![];
+        for &(i, line) in lines {
+            let parts = line
+                .split([' ', '\t', ','])
+                .filter(|part| !part.is_empty())
+                .collect::<Vec<_>>();
+            let &[state, read, write, shift, next] = parts.as_slice() else {
+                return Err(BadTuple(i));
+            };
+            check_state_name(state)?;
+            check_state_name(next)?;
+            let read = parse_symbol(read)?;
+            let transition = Transition {
+                write: parse_symbol(write)?,
+                shift: Move::parse(shift)?,
+                next: next.to_owned(),
+            };
+
+            let index = match states.iter().position(|(name, _)| name == state) {
+                Some(index) => index,
+                None => {
+                    states.push((state.to_owned(), [None, None]));
+                    states.len() - 1
+                }
+            };
+            let slot = &mut states[index].1[read as usize];
+            if slot.is_some() {
+                return Err(Duplicate(state.to_owned(), read as u8));
+            }
+            *slot = Some(transition);
+        }
+        Ok(Self { states })
+    }
+
+    /// Whether a state has any transitions. States without any halt.
+    fn is_state(&self, name: &str) -> bool {
+        self.states.iter().any(|(state, _)| state == name)
+    }
+
+    /// Writes a `.rie` program that runs the machine on a single register.
+    ///
+    /// The register only extends to the right of where it starts,
+    /// so the tape is folded in half at its starting cell, and laid out in blocks of 3 cells:
+    /// a marker which is only set in the first block, then cells `n` and `-1 - n` of the tape.
+    /// Every state has a routine for either half of the tape, named like `right_A` and `left_A`,
+    /// and the head of the register stays on the cell of the tape that the machine is on.
+    pub fn to_rie(&self) -> String {
+        let mut rie = RieSource::new();
+        rie.comment("generated by rieasm import-tm");
+        rie.comment(&format!(
+            "a Turing machine with {} states and 2 symbols, running on the `{TAPE}` register.",
+            self.states.len()
+        ));
+        rie.comment("the tape is folded in half onto the register, in blocks of 3 cells:");
+        rie.comment("a marker that is only set in the first block, then cell n of the tape, then cell -1-n.");
+        rie.comment("every state has a routine for each half of the tape.");
+        rie.comment("");
+        rie.comment("transitions (state, read, write, move, next):");
+        for (name, transitions) in &self.states {
+            for (read, transition) in transitions.iter().enumerate() {
+                let text = match transition {
+                    Some(t) => format!("{name} {read} {} {} {}", t.write as u8, t.shift, t.next),
+                    None => format!("{name} {read} halt"),
+                };
+                rie.comment(&format!("    {text}"));
+            }
+        }
+        rie.blank();
+        rie.line(&format!("state\targ\tgoto\tread\treg:{TAPE}"));
+        rie.line("entry\tstart");
+        rie.blank();
+
+        rie.open("routine start");
+        rie.line(&format!(
+            "%@{TAPE} >@{TAPE}\t-- mark the first block, then move to cell 0"
+        ));
+        self.goto(&mut rie, &self.states[0].0, true);
+        rie.close();
+
+        for (name, transitions) in &self.states {
+            for right in [true, false] {
+                rie.blank();
+                let half = if right { "right" } else { "left" };
+                rie.comment(&format!("{name}, on the {half} half of the tape"));
+                rie.open(&format!("routine {half}_{name}"));
+                rie.open(&format!("if {TAPE}?"));
+                self.transition(&mut rie, transitions[1].as_ref(), true, right);
+                rie.otherwise();
+                self.transition(&mut rie, transitions[0].as_ref(), false, right);
+                rie.close();
+                rie.close();
+            }
+        }
+        rie.finish()
+    }
+
+    /// Goes to the routine of a state for the half of the tape the head ends up on.
+    fn goto(&self, rie: &mut RieSource, next: &str, right: bool) {
+        if self.is_state(next) {
+            let half = if right { "right" } else { "left" };
+            rie.line(&format!("goto {half}_{next}"));
+        } else {
+            rie.line("halt");
+        }
+    }
+
+    /// Runs a transition from a cell holding `read` on one half of the tape.
+    fn transition(
+        &self,
+        rie: &mut RieSource,
+        transition: Option<&Transition>,
+        read: bool,
+        right: bool,
+    ) {
+        let Some(transition) = transition else {
+            rie.line("halt");
+            return;
+        };
+        if transition.write != read {
+            rie.line(&format!("%@{TAPE}"));
+        }
+        // moving away from the fold is the same on both halves, but moving towards it needs to check for the marker,
+        // which is 1 cell behind on the right half and 2 on the left
+        let outwards = (transition.shift == Move::Right) == right;
+        let next = &transition.next;
+        if outwards {
+            rie.line(&format!(">>>@{TAPE}"));
+            self.goto(rie, next, right);
+            return;
+        }
+        let (to_marker, to_other_half, to_previous) = if right {
+            ("<", ">>", "<<")
+        } else {
+            ("<<", ">", "<")
+        };
+        rie.open(&format!("if {to_marker}@{TAPE} {TAPE}?"));
+        rie.line(&format!("{to_other_half}@{TAPE}"));
+        self.goto(rie, next, !right);
+        rie.otherwise();
+        rie.line(&format!("{to_previous}@{TAPE}"));
+        self.goto(rie, next, right);
+        rie.close();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::rie::{machine::run_to_halt, RieProgram};
+
+    const BB2: &str = "1RB1LB_1LA1RZ";
+
+    /// Runs the machine until it halts, and returns how many cells of its tape are set.
+    fn ones(tm: &TuringMachine) -> usize {
+        let (program, _warnings) = RieProgram::parse(&tm.to_rie()).unwrap();
+        let tape = run_to_halt(&program).registers[0].to_string();
+        // the marker of the first block is set too
+        tape.matches('1').count() - 1
+    }
+
+    #[test]
+    fn bb2_halts_with_4_ones() {
+        assert_eq!(ones(&TuringMachine::parse(BB2).unwrap()), 4);
+    }
+
+    #[test]
+    fn reads_5_tuples() {
+        let tuples = "\
+# BB2
+A 0 1 R B
+A 1 1 L B
+B 0 1 L A
+B,1,1,R,Z
+";
+        let tm = TuringMachine::parse(tuples).unwrap();
+        assert_eq!(tm, TuringMachine::parse(BB2).unwrap());
+        assert_eq!(ones(&tm), 4);
+    }
+
+    #[test]
+    fn rejects_bad_machines() {
+        assert!(matches!(TuringMachine::parse(""), Err(TmErr::Empty)));
+        assert!(matches!(
+            TuringMachine::parse("1RB1XB_1LA1RZ"),
+            Err(TmErr::BadTransition(_))
+        ));
+        assert!(matches!(
+            TuringMachine::parse("1RB_1LA1RZ"),
+            Err(TmErr::BadStateTransitions('A', _))
+        ));
+        assert!(matches!(
+            TuringMachine::parse("A 0 1 R B\nA 0 0 L B"),
+            Err(TmErr::Duplicate(_, 0))
+        ));
+        assert!(matches!(
+            TuringMachine::parse("A 0 2 R B"),
+            Err(TmErr::BadSymbol(_))
+        ));
+    }
+}
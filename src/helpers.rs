@@ -1,44 +1,4 @@
-use std::{
-    env,
-    fs::File,
-    io::{stdin, stdout, Write},
-    iter::repeat,
-    path::PathBuf,
-    str::FromStr,
-};
-
-pub const RED: &str = "\x1b[0;31;1m";
-pub const YELLOW: &str = "\x1b[0;33;1m";
-pub const GREEN: &str = "\x1b[0;32;1m";
-pub const RESET: &str = "\x1b[0m";
-
-pub fn find_file(filename: &str, exec_dir: Option<String>) -> Option<File> {
-    eprintln!("Searching for {filename}.");
-
-    macro_rules! try_dir {
-        ($dir:expr, $variant:tt, $notfound:expr) => {
-            if let $variant(dir) = $dir {
-                let mut dir = PathBuf::from(dir);
-                eprintln!("Searching in {dir:?}...");
-                dir.push(&filename);
-                if dir.is_file() {
-                    eprintln!("Found.");
-                    return Some(File::open(filename).expect("Could not open file."));
-                } else {
-                    eprintln!("Not in {dir:?}.");
-                }
-            } else {
-                eprintln!($notfound);
-            }
-        };
-    }
-
-    try_dir!(env::current_dir(), Ok, "Current dir not found.");
-    try_dir!(exec_dir, Some, "Executable dir not found.");
-
-    eprintln!("File not found.");
-    None
-}
+use std::{iter::repeat_n, str::FromStr};
 
 pub fn get_tokens(line: &str) -> impl Iterator<Item = &str> {
     debug_assert!(
@@ -54,6 +14,7 @@ pub fn next_token<'a, 'b, T: FromStr, E>(
 ) -> Result<Option<T>, E> {
     token_iter
         .next()
+        .map(str::trim)
         .filter(|token| !token.is_empty())
         .map_or(Ok(None), |token| {
             token
@@ -65,7 +26,7 @@ pub fn next_token<'a, 'b, T: FromStr, E>(
 
 pub fn extend_vec_to<T: Clone>(vec: &mut Vec<T>, item: T, len: usize) -> usize {
     let needed = len.saturating_sub(vec.len());
-    vec.extend(repeat(item).take(needed));
+    vec.extend(repeat_n(item, needed));
     needed
 }
 
@@ -80,33 +41,3 @@ pub fn break_string(string: &str, max_line_length: usize) -> String {
 pub fn largest_bit(n: usize) -> u32 {
     usize::BITS - n.leading_zeros()
 }
-
-pub fn print_flush(message: &str) {
-    eprint!("{message}");
-    stdout().flush().expect("Couldn't flush stdout.");
-}
-
-pub fn get_line() -> String {
-    let mut input = String::new();
-    stdin()
-        .read_line(&mut input)
-        .expect("Couldn't read line from stdin.");
-    input
-}
-
-pub fn pause() {
-    print_flush(&format!("[Enter]"));
-    get_line();
-}
-
-pub fn ask_y_n() -> bool {
-    loop {
-        print_flush(&format!("[{GREEN}Y{RESET}/{RED}N{RESET}]: "));
-        match get_line().trim().to_uppercase().chars().next() {
-            Some('Y') => return true,
-            Some('N') => return false,
-            None => return false,
-            _ => {}
-        }
-    }
-}
@@ -0,0 +1,23 @@
+//! The rieasm assembler, which turns `.rie` source into ROMs for the Flow6 Read-If/Else machine.
+//!
+//! Nothing in here reads from standard input or writes to standard error.
+//! Anything suspicious about a program is handed back as a list of [`rie::RieWarning`]s instead.
+//!
+//! ```
+//! use rieasm::rie::RieProgram;
+//!
+//! let source = "\
+//! \tstate\targ\tgoto\tread\treg
+//! \t0\tfalse\t1\ttrue\t>
+//! \t1\ttrue\t0\t\t?
+//! ";
+//! let (program, warnings) = RieProgram::parse(source).unwrap();
+//! assert!(warnings.is_empty());
+//!
+//! let mut rle = vec![];
+//! program.write_rle(&mut rle).unwrap();
+//! ```
+
+pub mod frontend;
+mod helpers;
+pub mod rie;
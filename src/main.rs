@@ -1,62 +1,201 @@
-mod helpers;
-mod rie;
+mod args;
+mod term;
 
 use crate::{
-    helpers::{ask_y_n, find_file, pause, GREEN, RED, RESET, YELLOW},
-    rie::{RieErr, RieProgram},
+    args::{Args, Command, Format, Input, Output},
+    term::{ask_y_n, is_interactive, pause, GREEN, RED, RESET, YELLOW},
+};
+use rieasm::frontend::{
+    bf::{BfErr, BfProgram},
+    flipifelse::{FieErr, FlipIfElse, PROG, TEMP},
+    minsky::{CounterMachine, MinskyErr},
+    tm::{TmErr, TuringMachine},
+};
+use rieasm::rie::{
+    assembler::Assembler,
+    disasm::DisasmErr,
+    formatter::format_source,
+    image::ImageErr,
+    json::JsonErr,
+    machine::{Machine, Tape},
+    rie_line::RieLine,
+    RieErr, RieProgram, RieWarning,
+};
+use std::{
+    env, fs,
+    io::{self, Read, Write},
+    path::{Path, PathBuf},
+    process::ExitCode,
+    sync::Arc,
 };
-use std::env;
 
+#[cfg(feature = "clipboard")]
 use copypasta::{ClipboardContext, ClipboardProvider};
 use thiserror::Error;
 
 const HELP_MSG: &str = "\
 Welcome to Rie!
 Usage:
-    cargo run (help)
-    cargo run <source>(.rie) (--clip)
+    rieasm help
+    rieasm <command> <source>(.rie) [flags]
 
-Examples:
-    cargo run
-    cargo run help
-        > Shows this message, hello user :)
+    When running through cargo, put the arguments after `--`, like `cargo run -- build program`.
 
-    cargo run program --clip
-        > Will find `program.rie` in your current directory, compile it into RLE, and put the RLE into your clipboard.
+Commands:
+    build
+        Compiles the source into a ROM for the Flow6 Turing Machine.
+    check
+        Only checks that the source compiles.
+    run
+        Simulates the program and shows what ends up in the registers.
+    disasm
+        Turns an RLE made by `build` back into `.rie` source.
+    fmt
+        Normalizes the whitespace of the source.
+    listing
+        Shows a readable representation of every instruction in the program.
+    import-tm
+        Turns a 2-symbol Turing machine into `.rie` source that runs it on a single register.
+        Takes bbchallenge's format, like `1RB1LB_1LA1RZ`, or a file of 5-tuples like `A 0 1 R B`.
+    import-minsky
+        Turns a counter machine into `.rie` source, with a register for every counter.
+        Takes a file of `inc <counter>`, `jzdec <counter>, <label>` and `halt` instructions, any of which may be labelled like `loop:`.
+    import-bf
+        Turns a Brainfuck program into `.rie` source, with 8-bit cells on the `mem` register.
+        `,` reads from the `in` register, which starts with everything after the first `!`, and `.` writes to `out`.
+    flipifelse
+        Turns a program for `golly/FlipIfElse.rie`, with lines like `loop: flip +1 if loop else next`, into the bits of its `prog` register.
+        Run it with `rieasm run golly/FlipIfElse --tape prog=<bits> --tape temp=1`.
 
-    (WINDOWS)
-    cargo run program > rom.rle
-    cargo run program.rie > rom.rle
-    (LINUX PROBABLY)
-    cargo run program | rom.rle
-    cargo run program.rie | rom.rle
+Examples:
+    rieasm build program --clip
+        > Will find `program.rie` in your current directory, compile it into RLE, and put the RLE into your clipboard.
 
+    rieasm build program -o rom.rle
+    rieasm build program.rie > rom.rle
         > Will compile `program.rie` and output into `rom.rle`
 
+    rieasm run program --steps 100 --trace
+        > Will show every instruction `program.rie` runs, stopping after 100 steps.
+
+    rieasm disasm rom.rle -o program.rie
+        > Will turn `rom.rle` back into `.rie` source.
+
+    rieasm import-tm bb2.txt -o bb2.rie
+        > Will turn the Turing machine in `bb2.txt` into `.rie` source in `bb2.rie`.
+
+    cat program.rie | rieasm fmt - -o program.rie
+        > Will format source from standard input and write it into `program.rie`.
+
 Arguments:
     <source>
-        The path to the `.rie` file containing source code.
-        The program will automatically append '.rie' to the path if it isn't already there.
+        The path to the `.rie` file containing source code, or `-` to read from standard input.
+        The program will automatically append '.rie' to the path if it isn't already there, unless it ends in '.json'.
+        For `disasm`, this is the path to an RLE instead.
 
 Flags:
+    --output <path> (or -o)
+        Writes the output to a file.
+        Otherwise uses standard output.
     --clip (or -c)
         Whether to output directly to clipboard.
-        Otherwise uses standard output, meaning you have to pipe it to an rle file yourself.
+    --format <format> (or -f)
+        What `build` should output. One of:
+            rle (default): an RLE to paste into Golly.
+            rie: the program as plain `.rie` source, one line per instruction.
+            json: the program as JSON, for other tools. Paths ending in `.json` are loaded back instead of assembled.
+            bin: the ROM as a packed binary image, one word per address, for other simulators.
+                Starts with `RIE1`, a byte of state bits, a byte of register count, and a 16-bit word width.
+            verilog: a Verilog module running the program, for HDL simulators. Define RIE_TESTBENCH for a test bench printing what `run` would.
+            c: a standalone C program running the program natively, printing what `run` would.
+                Takes the most steps to run as its argument, defaulting to --steps.
+    --include-dir <dir> (or -I)
+        Adds a directory to search for source files in. Can be used more than once.
+        Sources are searched for relative to the current directory first,
+        then in every --include-dir in order,
+        then in every directory listed in the RIEASM_PATH environment variable.
+    --steps <n>
+        How many instructions `run` may simulate before giving up. Defaults to 1000000.
+    --trace
+        Makes `run` show every instruction it simulates.
+    --tape <register>=<bits>
+        Starts a register with bits on it in `run`, written the way `run` shows them, like `--tape prog=1[0]01`.
+        The head starts on the bit in brackets, or the first bit without any. Can be used once per register.
+        Registers holding virtual registers can't be given a tape, since those expect it to start out blank.
+    --optimize (or -O)
+        Shortens the program before using it, by doing the work of consecutive states in one step where the
+        hardware allows, skipping states that only jump elsewhere, dropping states that can't be reached,
+        and merging states that behave the same, which can halve the ROM.
+    --fill <policy>
+        What entries that no line defines should do, overriding any `fill` directive. One of:
+            halt (default): halt, like the end of the program.
+            trap: go to an unused state that loops forever, so mistakes don't look like a clean halt.
+            loop: loop forever in place.
+    --listing <path>
+        Also writes a listing of every address of the ROM to a file,
+        with the bits of its column, the line of source it came from, and what it does.
+    --source-map <path>
+        Also writes a table of every cell of the RLE to a file, separated by tabs,
+        with the line of source, state, arg, and field (register, read, or goto bit) that the cell belongs to.
 ";
 
+#[cfg_attr(not(feature = "clipboard"), allow(dead_code))]
 type ClipErr = Box<dyn std::error::Error + Send + Sync>;
 
 #[derive(Error, Debug)]
 pub enum CLIErr {
     #[error(
-        "Could not find {0} inside target directory.\n\
-        {YELLOW}Please open a .rie file.{RESET}"
+        "Unknown command: {0}\n\
+        {YELLOW}Try `rieasm help`.{RESET}"
     )]
-    FileNotFound(String),
+    UnknownCommand(String),
+
+    #[error(
+        "Unknown format: {0}\n\
+        Valid formats: {:?}",
+        Format::FORMATS.map(|(name, _format)| name),
+    )]
+    UnknownFormat(String),
 
     #[error("Invalid flag: {0}")]
     InvalidFlag(String),
 
+    #[error("The {0} flag needs a value.")]
+    MissingValue(String),
+
+    #[error("Invalid value for the {0} flag: {1:?}")]
+    BadValue(String, String),
+
+    #[error("There is no register named {0:?} to put a --tape on.")]
+    UnknownTapeRegister(String),
+
+    #[error(
+        "Can't put a --tape on {0}, since the virtual registers {1:?} were put in it.\n\
+        Virtual registers expect their register to start out blank."
+    )]
+    TapeOnVirtualRegister(String, Vec<String>),
+
+    #[error("Unexpected argument: {0}")]
+    UnexpectedArg(String),
+
+    #[error("Only one of --output and --clip can be used at a time.")]
+    MultipleOutputs,
+
+    #[error(
+        "No source was given.\n\
+        {YELLOW}Please specify a file, or `-` to read from standard input.{RESET}"
+    )]
+    MissingSource,
+
+    #[error("IO Error: {0}")]
+    IO(#[from] io::Error),
+
+    #[error(
+        "Could not write to {0:?}.\n\
+        Exact error: {1}"
+    )]
+    WriteErr(PathBuf, io::Error),
+
     #[error(
         "Could not open clipboard.\n\
         Exact error: {0}"
@@ -69,70 +208,321 @@ pub enum CLIErr {
     )]
     WriteClipboardErr(ClipErr),
 
+    #[error("This build of rieasm was compiled without the clipboard feature.")]
+    NoClipboard,
+
+    #[error("Cancelled compilation.")]
+    Cancelled,
+
+    #[error("Could not disassemble the RLE: {0}")]
+    DisasmErr(#[from] DisasmErr),
+
+    #[error("Could not load the JSON: {0}")]
+    JsonErr(#[from] JsonErr),
+
+    #[error("Could not write the ROM image: {0}")]
+    ImageErr(#[from] ImageErr),
+
+    #[error("`fmt` only works on `.rie` source, not JSON.")]
+    FmtJson,
+
+    #[error("The {0} format can't be put into the clipboard. Please use --output instead.")]
+    BinaryClipboard(&'static str),
+
+    #[error("Could not import the Turing machine: {0}")]
+    TmErr(#[from] TmErr),
+
+    #[error("Could not import the counter machine: {0}")]
+    MinskyErr(#[from] MinskyErr),
+
+    #[error("Could not import the Brainfuck program: {0}")]
+    BfErr(#[from] BfErr),
+
+    #[error("Could not compile the FlipIfElse program: {0}")]
+    FieErr(#[from] FieErr),
+
     #[error("{0}")]
     RieErr(#[from] RieErr),
 }
 
-pub fn run_cli() -> Result<(), CLIErr> {
+/// Reads the input, along with the path it was found at.
+fn read_input(input: &Input, assembler: &Assembler) -> Result<(String, Arc<Path>), CLIErr> {
+    match input {
+        Input::Stdin => {
+            let mut source = String::new();
+            io::stdin().read_to_string(&mut source)?;
+            Ok((source, Arc::from(PathBuf::from("<stdin>"))))
+        }
+        Input::Path(filename) => {
+            eprintln!("Searching for {filename}.");
+            let path = assembler.search_path.resolve(filename, None)?;
+            eprintln!("Found {}.", path.display());
+            let source = fs::read_to_string(&path).map_err(|e| RieErr::ReadErr(path.clone(), e))?;
+            Ok((source, Arc::from(path)))
+        }
+    }
+}
+
+fn write_output(output: &Output, mut contents: String) -> Result<(), CLIErr> {
     use CLIErr::*;
 
-    let mut args = env::args();
-    let executable_dir = args.next();
-    let arg = args.next().unwrap_or_else(|| "help".to_owned());
-    let output_to_clip = args.next().map_or(Ok(false), |arg| {
-        (arg == "--clip" || arg == "-c")
-            .then(|| true)
-            .ok_or_else(|| InvalidFlag(arg.to_owned()))
-    })?;
+    if !contents.ends_with('\n') {
+        contents.push('\n');
+    }
+    match output {
+        Output::Stdout => {
+            print!("{contents}");
+            eprintln!("Output sent to standard output.");
+        }
+        #[cfg(feature = "clipboard")]
+        Output::Clipboard => {
+            let mut clip = ClipboardContext::new().map_err(OpenClipboardErr)?;
+            clip.set_contents(contents).map_err(WriteClipboardErr)?;
+            eprintln!("(Check your clipboard)");
+        }
+        #[cfg(not(feature = "clipboard"))]
+        Output::Clipboard => return Err(NoClipboard),
+        Output::File(path) => {
+            fs::write(path, contents).map_err(|e| WriteErr(path.to_owned(), e))?;
+            eprintln!("Output written to {path:?}.");
+        }
+    }
+    Ok(())
+}
+
+/// Writes output that isn't text, which can't go to the clipboard.
+fn write_binary_output(
+    output: &Output,
+    contents: &[u8],
+    format: &'static str,
+) -> Result<(), CLIErr> {
+    use CLIErr::*;
+
+    match output {
+        Output::Stdout => {
+            io::stdout().write_all(contents)?;
+            eprintln!("Output sent to standard output.");
+        }
+        Output::Clipboard => return Err(BinaryClipboard(format)),
+        Output::File(path) => {
+            fs::write(path, contents).map_err(|e| WriteErr(path.to_owned(), e))?;
+            eprintln!("Output written to {path:?}.");
+        }
+    }
+    Ok(())
+}
+
+/// Shows every warning, and asks whether to continue if the program is suspiciously large.
+/// Without `can_ask`, large programs are only warned about.
+fn report_warnings(warnings: &[RieWarning], can_ask: bool) -> Result<(), CLIErr> {
+    for warning in warnings {
+        match warning {
+            RieWarning::LargeProgram { .. } if can_ask => {
+                eprintln!(
+                    "{YELLOW}WAIT!{RESET}\n\
+                    {warning}\n\
+                    Are you sure you want a machine with that many instructions?"
+                );
+                if !ask_y_n() {
+                    return Err(CLIErr::Cancelled);
+                }
+                eprintln!("Continuing...");
+            }
+            _ => eprintln!("{YELLOW}Warning: {warning}{RESET}"),
+        }
+    }
+    Ok(())
+}
+
+fn simulate(
+    program: &RieProgram,
+    max_steps: u64,
+    trace: bool,
+    tapes: &[(String, Tape)],
+) -> Result<String, CLIErr> {
+    let mut out = String::new();
+    let mut machine = Machine::new(program);
+    for (register, tape) in tapes {
+        let index = program
+            .register_names()
+            .find(register)
+            .ok_or_else(|| CLIErr::UnknownTapeRegister(register.clone()))?;
+        let virtual_registers = program.virtual_registers(index);
+        if !virtual_registers.is_empty() {
+            return Err(CLIErr::TapeOnVirtualRegister(
+                program.register_names().describe(index),
+                virtual_registers.to_vec(),
+            ));
+        }
+        machine.registers[index] = tape.clone();
+    }
+    if trace {
+        let state_digits = program.state_digits();
+        while !machine.halted && machine.steps < max_steps {
+            let cmd = machine.current(program);
+            out.push_str(&format!(
+                "{:>6}: {}\n",
+                machine.steps,
+                RieLine::to_string(
+                    state_digits,
+                    program.register_names(),
+                    machine.state,
+                    machine.arg,
+                    cmd
+                )
+            ));
+            machine.step(program);
+            out.push_str(&machine.show_registers(program));
+        }
+    } else {
+        machine.run(program, max_steps);
+    }
+
+    out.push_str(&machine.report(program));
+    Ok(out)
+}
 
-    if arg == "help" {
+pub fn run_cli() -> Result<(), CLIErr> {
+    use CLIErr::*;
+
+    let args = Args::parse(env::args().skip(1))?;
+    if args.command == Command::Help {
         eprintln!("{HELP_MSG}");
         return Ok(());
     }
-    let mut filename = arg;
+    let mut assembler = Assembler::new();
+    assembler.fill = args.fill;
+    for dir in args.include_dirs.iter() {
+        assembler.search_path.push(dir);
+    }
+    assembler.search_path.push_env();
+
+    let input = args.input.as_ref().ok_or(MissingSource)?;
+    let (source, path) = read_input(input, &assembler)?;
+
+    if args.command == Command::Disasm {
+        let program = RieProgram::disassemble(&source)?;
+        eprintln!("{GREEN}RLE successfully disassembled.{RESET}");
+        return write_output(&args.output, program.to_rie());
+    }
 
-    // open rie file
-    if !filename.ends_with(".rie") {
-        filename.push_str(".rie");
+    if args.command == Command::ImportTm {
+        let machine = TuringMachine::parse(&source)?;
+        eprintln!("{GREEN}Turing machine successfully imported.{RESET}");
+        return write_output(&args.output, machine.to_rie());
     }
-    let file =
-        find_file(&filename, executable_dir).ok_or_else(|| FileNotFound(filename.to_owned()))?;
 
-    // File -> IR
-    let program_ir = RieProgram::try_from(file)?;
-    eprintln!("{GREEN}Program successfully parsed.{RESET}");
+    if args.command == Command::ImportMinsky {
+        let machine = CounterMachine::parse(&source)?;
+        eprintln!("{GREEN}Counter machine successfully imported.{RESET}");
+        return write_output(&args.output, machine.to_rie());
+    }
 
-    // IR -> RLE -> out
-    let rle = program_ir.rle();
-    eprintln!("{GREEN}Program successfully compiled.{RESET}");
-    if output_to_clip {
-        let mut clip = ClipboardContext::new().map_err(OpenClipboardErr)?;
-        clip.set_contents(rle).map_err(WriteClipboardErr)?;
-        eprintln!("(Check your clipboard)");
-    } else {
-        println!("{rle}");
-        eprintln!("RLE sent to standard output.");
+    if args.command == Command::ImportBf {
+        let program = BfProgram::parse(&source)?;
+        eprintln!("{GREEN}Brainfuck program successfully imported.{RESET}");
+        return write_output(&args.output, program.to_rie());
     }
 
-    eprintln!("Would you like to view the program's representation?");
-    eprintln!("({YELLOW}{} lines of code{RESET})", program_ir.len());
-    if ask_y_n() {
-        for (i, line) in program_ir.to_string().split('\n').enumerate() {
-            eprintln!("{line}");
-            if i % 8 == 0 {
-                pause()
-            }
+    if args.command == Command::FlipIfElse {
+        let program = FlipIfElse::parse(&source)?;
+        let tape = program.prog_tape();
+        eprintln!(
+            "{GREEN}FlipIfElse program successfully compiled.{RESET}\n\
+            Run it with `rieasm run golly/FlipIfElse --tape {PROG}={tape} --tape {TEMP}=1`."
+        );
+        return write_output(&args.output, tape);
+    }
+
+    // formatting only looks at the text, so it works on files that don't assemble yet
+    if args.command == Command::Fmt {
+        if input.is_json() {
+            return Err(FmtJson);
         }
-        eprintln!("End.");
-        pause()
+        return write_output(&args.output, format_source(&source));
     }
 
-    Ok(())
+    // Source -> IR
+    let mut program = if input.is_json() {
+        let program = RieProgram::from_json(&source)?;
+        eprintln!("{GREEN}Program successfully loaded.{RESET}");
+        program
+    } else {
+        eprintln!("Compiling to IR...");
+        let (program, warnings) = assembler.parse_str(&source, path)?;
+        report_warnings(&warnings, *input != Input::Stdin && is_interactive())?;
+        eprintln!("{GREEN}Program successfully parsed.{RESET}");
+        program
+    };
+    if program.split_states() > 0 {
+        eprintln!(
+            "Split multi-step commands using {} extra states.",
+            program.split_states()
+        );
+    }
+    if args.optimize {
+        let optimizations = program.optimize();
+        eprintln!("{optimizations}");
+    }
+    if let Some(path) = &args.listing {
+        fs::write(path, program.address_listing()).map_err(|e| WriteErr(path.clone(), e))?;
+        eprintln!("Listing written to {path:?}.");
+    }
+    if let Some(path) = &args.source_map {
+        fs::write(path, program.source_map()).map_err(|e| WriteErr(path.clone(), e))?;
+        eprintln!("Source map written to {path:?}.");
+    }
+
+    let output = match args.command {
+        Command::Check => {
+            eprintln!(
+                "{YELLOW}{} lines of code{RESET}, with {} state bits and {} registers.",
+                program.len(),
+                program.state_bits(),
+                program.register_count(),
+            );
+            return Ok(());
+        }
+        Command::Build => {
+            let output = match args.format {
+                Format::Rle => program.rle(),
+                Format::Rie => program.to_rie(),
+                Format::Json => program.to_json(),
+                Format::Verilog => program.to_verilog(),
+                Format::C => program.to_c(args.max_steps),
+                Format::Bin => {
+                    eprintln!("{GREEN}Program successfully compiled.{RESET}");
+                    return write_binary_output(&args.output, &program.rom_image()?, "bin");
+                }
+            };
+            eprintln!("{GREEN}Program successfully compiled.{RESET}");
+            output
+        }
+        Command::Run => simulate(&program, args.max_steps, args.trace, &args.tapes)?,
+        Command::Listing => program.to_string(),
+        Command::Help
+        | Command::Fmt
+        | Command::Disasm
+        | Command::ImportTm
+        | Command::ImportMinsky
+        | Command::ImportBf
+        | Command::FlipIfElse => {
+            unreachable!()
+        }
+    };
+    write_output(&args.output, output)
 }
 
-fn main() {
-    if let Err(e) = run_cli() {
-        eprintln!("{RED}Error: {e}{RESET}");
-        pause();
+fn main() -> ExitCode {
+    match run_cli() {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(e) => {
+            eprintln!("{RED}Error: {e}{RESET}");
+            // keep the window open when run by double-clicking
+            if is_interactive() {
+                pause();
+            }
+            ExitCode::FAILURE
+        }
     }
 }
@@ -0,0 +1,323 @@
+use super::{
+    header::{HeaderFormat, RegisterNames},
+    register_cmd::RegisterCmd,
+    source::SourcePos,
+    tm_cmd::TMCmd,
+    RieErr,
+};
+use std::collections::BTreeSet;
+
+/// The entries (`state * 2 + arg`) that a command can go to next, if it reads `read`.
+fn nexts(cmd: &TMCmd, read: Option<bool>) -> Vec<usize> {
+    let next = cmd.goto as usize * 2;
+    let reads_register = cmd.register_cmds.contains(&RegisterCmd::Read);
+    match read {
+        Some(bit) => vec![next + bit as usize],
+        None if reads_register => vec![next, next + 1],
+        // no read means the machine halts here
+        None => vec![],
+    }
+}
+
+/// The entries that each entry of the program can go to next.
+fn successors(commands: &[[TMCmd; 2]]) -> Vec<Vec<usize>> {
+    commands
+        .iter()
+        .flatten()
+        .map(|cmd| nexts(cmd, cmd.read))
+        .collect()
+}
+
+/// What a register column holds when the machine gets to an entry,
+/// going only by the commands in that column.
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Contents {
+    /// The machine never gets there.
+    Unreached,
+    /// The offset from the head of every set cell.
+    Known(BTreeSet<i64>),
+    /// Different ways of getting there leave different contents.
+    Unknown,
+}
+
+impl Contents {
+    /// Merges the contents of another way of getting to the same entry, returning whether anything changed.
+    fn join(&mut self, other: &Self) -> bool {
+        use Contents::*;
+        let joined = match (&*self, other) {
+            (_, Unreached) => return false,
+            (Unreached, other) => other.clone(),
+            (Known(a), Known(b)) if a == b => return false,
+            (Unknown, _) => return false,
+            _ => Unknown,
+        };
+        *self = joined;
+        true
+    }
+
+    /// Whether the column is blank, which looks the same wherever the head is.
+    fn is_blank(&self) -> bool {
+        match self {
+            Self::Unreached => true,
+            Self::Known(cells) => cells.is_empty(),
+            Self::Unknown => false,
+        }
+    }
+}
+
+/// What a column holds at every entry, starting out blank at state 0 with arg false.
+fn contents(commands: &[[TMCmd; 2]], column: usize) -> Vec<Contents> {
+    use RegisterCmd::*;
+
+    let mut contents = vec![Contents::Unreached; commands.len() * 2];
+    contents[0] = Contents::Known(BTreeSet::new());
+    let mut stack = vec![0];
+    while let Some(entry) = stack.pop() {
+        let cmd = &commands[entry / 2][entry % 2];
+        let mut after = contents[entry].clone();
+        let mut read = cmd.read;
+        if let Contents::Known(cells) = &mut after {
+            let shift = |cells: &mut BTreeSet<i64>, by: i64| {
+                *cells = cells.iter().map(|cell| cell + by).collect();
+            };
+            let flip = |cells: &mut BTreeSet<i64>| {
+                if !cells.remove(&0) {
+                    cells.insert(0);
+                }
+            };
+            match cmd.register_cmds.get(column).copied().unwrap_or_default() {
+                Noop => {}
+                Push => shift(cells, -1),
+                Pull => shift(cells, 1),
+                Flip => flip(cells),
+                FlipPull => {
+                    flip(cells);
+                    shift(cells, 1);
+                }
+                // reading a known cell only goes one way
+                Read => read = Some(cells.contains(&0)),
+            }
+        }
+        for next in nexts(cmd, read) {
+            if contents[next].join(&after) {
+                stack.push(next);
+            }
+        }
+    }
+    contents
+}
+
+/// Every entry that can be reached from `starts`, including `starts` themselves.
+fn reachable(starts: &[usize], edges: &[Vec<usize>]) -> Vec<bool> {
+    let mut seen = vec![false; edges.len()];
+    let mut stack = starts.to_vec();
+    while let Some(entry) = stack.pop() {
+        if seen[entry] {
+            continue;
+        }
+        seen[entry] = true;
+        stack.extend(&edges[entry]);
+    }
+    seen
+}
+
+/// The entries where a register column holds something that matters:
+/// anywhere on a path from one command that uses it to another.
+fn live_range(
+    commands: &[[TMCmd; 2]],
+    column: usize,
+    forward: &[Vec<usize>],
+    backward: &[Vec<usize>],
+) -> Vec<bool> {
+    let uses = commands
+        .iter()
+        .flatten()
+        .enumerate()
+        .filter(|(_entry, cmd)| {
+            cmd.register_cmds
+                .get(column)
+                .is_some_and(|&cmd| cmd != RegisterCmd::Noop)
+        })
+        .map(|(entry, _cmd)| entry)
+        .collect::<Vec<_>>();
+    let after = reachable(&uses, forward);
+    let before = reachable(&uses, backward);
+    after.iter().zip(before).map(|(&a, b)| a && b).collect()
+}
+
+/// A column put in a physical register.
+struct Occupant {
+    live: Vec<bool>,
+    contents: Vec<Contents>,
+}
+
+impl Occupant {
+    fn new(
+        commands: &[[TMCmd; 2]],
+        column: usize,
+        forward: &[Vec<usize>],
+        backward: &[Vec<usize>],
+    ) -> Self {
+        Self {
+            live: live_range(commands, column, forward, backward),
+            contents: contents(commands, column),
+        }
+    }
+
+    /// Whether two columns can share a register: they're never in use at the same time,
+    /// and each is blank whenever the other is in use, so neither sees what the other left behind.
+    fn can_share(&self, other: &Self) -> bool {
+        let blank_during = |a: &Self, b: &Self| {
+            a.contents
+                .iter()
+                .zip(&b.live)
+                .all(|(contents, &live)| !live || contents.is_blank())
+        };
+        self.live.iter().zip(&other.live).all(|(&a, &b)| !(a && b))
+            && blank_during(self, other)
+            && blank_during(other, self)
+    }
+}
+
+/// Rewrites commands written for every register column in `header` so that they only use physical registers,
+/// putting each virtual register in a physical register whose other columns are never in use at the same time as it,
+/// and are blank whenever it is.
+/// `first_uses` is where each column is first used, for error messages.
+///
+/// Returns the names of the physical registers, and the names of the virtual registers put in each one.
+pub fn allocate_registers(
+    commands: &mut [[TMCmd; 2]],
+    header: &HeaderFormat,
+    first_uses: &[Option<SourcePos>],
+) -> Result<(RegisterNames, Vec<Vec<String>>), RieErr> {
+    let physical = (0..header.column_count())
+        .filter(|&column| !header.is_virtual[column])
+        .collect::<Vec<_>>();
+    let physical_names = RegisterNames(
+        physical
+            .iter()
+            .map(|&column| header.register_names.0[column].clone())
+            .collect(),
+    );
+    let mut virtual_names = vec![vec![]; physical.len()];
+    if physical.len() == header.column_count() {
+        return Ok((physical_names, virtual_names));
+    }
+
+    let forward = successors(commands);
+    let mut backward = vec![vec![]; forward.len()];
+    for (entry, nexts) in forward.iter().enumerate() {
+        for &next in nexts {
+            backward[next].push(entry);
+        }
+    }
+
+    // physical registers stay where they are
+    let mut targets = vec![None; header.column_count()];
+    let mut occupants = vec![];
+    for (register, &column) in physical.iter().enumerate() {
+        targets[column] = Some(register);
+        occupants.push(vec![Occupant::new(commands, column, &forward, &backward)]);
+    }
+
+    // virtual registers go in the first physical register they can share with everything in
+    for column in (0..header.column_count()).filter(|&column| header.is_virtual[column]) {
+        let Some(pos) = &first_uses[column] else {
+            // never used, so it doesn't need a place
+            continue;
+        };
+        let occupant = Occupant::new(commands, column, &forward, &backward);
+        let register = occupants
+            .iter()
+            .position(|others: &Vec<Occupant>| others.iter().all(|other| occupant.can_share(other)))
+            .ok_or_else(|| {
+                RieErr::NoRoomForRegister(pos.clone(), header.register_names.describe(column))
+            })?;
+        occupants[register].push(occupant);
+        targets[column] = Some(register);
+        virtual_names[register].push(header.register_names.describe(column));
+    }
+
+    for cmd in commands.iter_mut().flatten() {
+        let mut register_cmds = vec![RegisterCmd::Noop; physical.len()];
+        for (column, &register_cmd) in cmd.register_cmds.iter().enumerate() {
+            if register_cmd == RegisterCmd::Noop {
+                continue;
+            }
+            let register = targets[column].expect("Every used column has a register.");
+            debug_assert_eq!(
+                register_cmds[register],
+                RegisterCmd::Noop,
+                "Two registers sharing a column were used at the same time."
+            );
+            register_cmds[register] = register_cmd;
+        }
+        cmd.register_cmds = register_cmds;
+    }
+
+    Ok((physical_names, virtual_names))
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::rie::{machine::run_to_halt, RieErr, RieProgram};
+
+    /// Checks that a program runs the same as it does with every virtual register made physical,
+    /// comparing the first `compared` registers, and returns how many registers it was given.
+    fn runs_the_same(source: &str, compared: usize) -> usize {
+        let (program, _warnings) = RieProgram::parse(source).unwrap();
+        let (physical, _warnings) = RieProgram::parse(&source.replace("vreg", "reg")).unwrap();
+        let (allocated, unallocated) = (run_to_halt(&program), run_to_halt(&physical));
+        assert_eq!(
+            (allocated.state, allocated.arg, allocated.steps),
+            (unallocated.state, unallocated.arg, unallocated.steps)
+        );
+        for register in 0..compared {
+            assert_eq!(
+                allocated.registers[register].to_string(),
+                unallocated.registers[register].to_string()
+            );
+        }
+        program.register_count()
+    }
+
+    // `a` leaves a bit behind, which `b` would read if they shared a register
+    const DIRTY: &str = "\
+\t0\tfalse\t1\tfalse\t\t\t%
+\t1\tfalse\t2\t\t\t\t\t?
+\t2\tfalse\t2\t\t%
+\t2\ttrue\t2
+";
+
+    // `a` flips its cell back, so `b` can have its register
+    const CLEAN: &str = "\
+\t0\tfalse\t1\tfalse\t\t%
+\t1\tfalse\t2\tfalse\t\t%
+\t2\tfalse\t3\t\t\t\t?
+\t3\tfalse\t3\t\t%
+\t3\ttrue\t3
+";
+
+    #[test]
+    fn dirty_registers_dont_share() {
+        let header = "\tstate\targ\tgoto\tread\treg:x\treg:y\tvreg:a\tvreg:b\n";
+        // `y` ends up holding `a`, so only `x` is compared
+        assert_eq!(runs_the_same(&format!("{header}{DIRTY}"), 1), 2);
+
+        let header = "\tstate\targ\tgoto\tread\treg:x\tvreg:a\tvreg:b\n";
+        let dirty = DIRTY
+            .replace("\t\t\t%", "\t\t%")
+            .replace("\t\t\t\t\t?", "\t\t\t\t?");
+        let result = RieProgram::parse(&format!("{header}{dirty}"));
+        assert!(matches!(result, Err(RieErr::NoRoomForRegister(..))));
+    }
+
+    #[test]
+    fn clean_registers_share() {
+        let header = "\tstate\targ\tgoto\tread\treg:x\tvreg:a\tvreg:b\n";
+        assert_eq!(runs_the_same(&format!("{header}{CLEAN}"), 1), 1);
+        // so `x` can't be given a --tape
+        let (program, _warnings) = RieProgram::parse(&format!("{header}{CLEAN}")).unwrap();
+        assert_eq!(program.virtual_registers(0), ["a", "b"]);
+    }
+}
@@ -0,0 +1,454 @@
+use super::{
+    allocator::allocate_registers, control_flow::lower, label::StateRef, loader::Loader,
+    register_cmd::RegisterCmd, rie_line::RieLine, search_path::SearchPath, source::SourcePos,
+    tm_cmd::TMCmd, RieErr, RieProgram, RieWarning,
+};
+use crate::helpers::{extend_vec_to, largest_bit};
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::{BTreeSet, HashMap},
+    fs,
+    io::BufRead,
+    path::Path,
+    str::FromStr,
+    sync::Arc,
+};
+
+/// What entries of the ROM that no line defines should do.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum FillPolicy {
+    /// Halt, as if the program ended normally.
+    #[default]
+    Halt,
+    /// Go to a state of their own that loops forever.
+    Trap,
+    /// Loop forever in place.
+    Loop,
+}
+
+impl FillPolicy {
+    pub const POLICIES: [(&'static str, Self); 3] = [
+        ("halt", Self::Halt),
+        ("trap", Self::Trap),
+        ("loop", Self::Loop),
+    ];
+
+    /// The command for an undefined entry at `state` with `arg`,
+    /// where `trap` is the state that traps go to.
+    pub(crate) fn fill(self, state: u32, arg: bool, trap: u32) -> TMCmd {
+        match self {
+            Self::Halt => TMCmd::default(),
+            Self::Trap => TMCmd {
+                goto: trap,
+                read: Some(false),
+                register_cmds: vec![],
+            },
+            Self::Loop => TMCmd {
+                goto: state,
+                read: Some(arg),
+                register_cmds: vec![],
+            },
+        }
+    }
+}
+
+impl FromStr for FillPolicy {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Self::POLICIES
+            .iter()
+            .find_map(|&(name, policy)| (s == name).then_some(policy))
+            .ok_or(())
+    }
+}
+
+/// Gives every label the lowest state number that isn't written anywhere in the program,
+/// in the order the labels are first defined.
+/// Gotos with offsets from a number, like `5+1`, count as writing that number,
+/// and gotos with offsets from a label, like `loop+3`, keep later labels off of the state they land on.
+fn allocate_labels(lines: &[(SourcePos, RieLine)]) -> HashMap<String, u32> {
+    let mut used = lines
+        .iter()
+        .flat_map(|(_pos, line)| [Some(&line.state), line.goto.as_ref()])
+        .filter_map(|state| state?.fixed_number())
+        .collect::<BTreeSet<_>>();
+    let mut offsets: HashMap<&str, Vec<i64>> = HashMap::new();
+    for (_pos, line) in lines {
+        if let Some(StateRef::Offset(base, offset)) = &line.goto {
+            if let StateRef::Label(label) = base.as_ref() {
+                offsets.entry(label).or_default().push(*offset);
+            }
+        }
+    }
+
+    let mut labels = HashMap::new();
+    let mut next = 0;
+    for (_pos, line) in lines {
+        if let StateRef::Label(label) = &line.state {
+            if labels.contains_key(label) {
+                continue;
+            }
+            while used.contains(&next) {
+                next += 1;
+            }
+            labels.insert(label.clone(), next);
+            used.insert(next);
+            for offset in offsets.get(label.as_str()).into_iter().flatten() {
+                if let Ok(target) = u32::try_from(next as i64 + offset) {
+                    used.insert(target);
+                }
+            }
+        }
+    }
+    labels
+}
+
+/// Finds the number of a state, now that every label has one.
+fn resolve_state(
+    labels: &HashMap<String, u32>,
+    pos: &SourcePos,
+    state: &StateRef,
+) -> Result<u32, RieErr> {
+    match state {
+        StateRef::Number(number) => Ok(*number),
+        StateRef::Label(label) => labels
+            .get(label)
+            .copied()
+            .ok_or_else(|| RieErr::UndefinedLabel(pos.clone(), label.clone())),
+        StateRef::Offset(base, offset) => {
+            let number = resolve_state(labels, pos, base)? as i64 + offset;
+            number
+                .try_into()
+                .map_err(|_| RieErr::BadOffset(pos.clone(), state.clone(), number))
+        }
+    }
+}
+
+/// Settings for turning `.rie` source code into a [`RieProgram`].
+#[derive(Debug, Clone, Default)]
+pub struct Assembler {
+    pub search_path: SearchPath,
+    /// What undefined entries should do, overriding any `fill` directive in the source.
+    pub fill: Option<FillPolicy>,
+}
+
+impl Assembler {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Finds a `.rie` file through the search path, then parses it.
+    pub fn parse_file(&self, name: &str) -> Result<(RieProgram, Vec<RieWarning>), RieErr> {
+        let path = self.search_path.resolve(name, None)?;
+        let source = fs::read_to_string(&path).map_err(|e| RieErr::ReadErr(path.clone(), e))?;
+        self.parse_str(&source, Arc::from(path))
+    }
+
+    /// Parses source code, reporting errors as if it came from `file`.
+    pub fn parse_str(
+        &self,
+        source: &str,
+        file: Arc<Path>,
+    ) -> Result<(RieProgram, Vec<RieWarning>), RieErr> {
+        self.parse_reader(source.as_bytes(), file)
+    }
+
+    pub fn parse_reader(
+        &self,
+        reader: impl BufRead,
+        file: Arc<Path>,
+    ) -> Result<(RieProgram, Vec<RieWarning>), RieErr> {
+        use RieErr::*;
+
+        let (header, code, settings) = Loader::new(&self.search_path).load_root(reader, file)?;
+        let (lines, split_states) = lower(code, &header.register_names)?;
+        let labels = allocate_labels(&lines);
+        let resolve = |pos: &SourcePos, state: &StateRef| resolve_state(&labels, pos, state);
+
+        let mut warning_size = 1 << 6;
+        let mut warnings = vec![];
+        let mut commands = vec![];
+        let mut add_cmd = |pos: SourcePos, state: u32, arg: bool, tm_cmd: TMCmd| {
+            let extension = state.max(tm_cmd.goto) as usize;
+
+            // warn once for every power of two the program grows beyond
+            if extension > warning_size {
+                let total_cmds = 2 << largest_bit(extension);
+                warnings.push(RieWarning::LargeProgram {
+                    pos,
+                    state,
+                    goto: tm_cmd.goto,
+                    total_cmds,
+                });
+                warning_size = total_cmds >> 1;
+            }
+
+            extend_vec_to(
+                &mut commands,
+                [TMCmd::default(), TMCmd::default()],
+                extension + 1,
+            );
+            commands[state as usize][arg as usize] = tm_cmd;
+        };
+
+        let mut first_uses = vec![None; header.column_count()];
+        let mut definitions = HashMap::new();
+        for (pos, line) in lines.iter() {
+            for (column, _cmd) in line
+                .register_cmds
+                .iter()
+                .enumerate()
+                .filter(|(_column, cmd)| **cmd != RegisterCmd::Noop)
+            {
+                first_uses[column].get_or_insert_with(|| pos.clone());
+            }
+            let state = resolve(pos, &line.state)?;
+            if let Some(first) = definitions.insert((state, line.arg), pos.clone()) {
+                return Err(DuplicateDefinition(
+                    pos.clone(),
+                    first,
+                    line.state.clone(),
+                    line.arg,
+                ));
+            }
+            let cmd = line.to_cmd(state, |goto| resolve(pos, goto))?;
+            // offsets from a label can still land on an earlier label, whose number is up to the assembler
+            if let Some(goto @ StateRef::Offset(base, _offset)) = &line.goto {
+                if let StateRef::Label(_) = base.as_ref() {
+                    if let Some((label, _number)) =
+                        labels.iter().find(|(_label, &number)| number == cmd.goto)
+                    {
+                        return Err(OffsetOntoLabel(
+                            pos.clone(),
+                            goto.clone(),
+                            cmd.goto,
+                            label.clone(),
+                        ));
+                    }
+                }
+            }
+            add_cmd(pos.clone(), state, line.arg, cmd);
+        }
+
+        // the machine starts at state 0 with arg false, so the entry trades numbers with it.
+        // starting with arg true needs a state of its own that goes to the entry with a read of true,
+        // so that going to the entry with arg false still does what it would have
+        if let Some((pos, state, arg)) = &settings.entry {
+            let entry = resolve(pos, state)?;
+            let start = match arg {
+                false => entry,
+                true => (0..)
+                    .find(|&state| {
+                        !definitions.contains_key(&(state, false))
+                            && !definitions.contains_key(&(state, true))
+                    })
+                    .expect("A program can't define every state."),
+            };
+            let swap = |state: u32| match state {
+                0 => start,
+                state if state == start => 0,
+                state => state,
+            };
+            extend_vec_to(
+                &mut commands,
+                [TMCmd::default(), TMCmd::default()],
+                start.max(entry) as usize + 1,
+            );
+            commands.swap(0, start as usize);
+            for cmd in commands.iter_mut().flatten() {
+                cmd.goto = swap(cmd.goto);
+            }
+            definitions = definitions
+                .into_iter()
+                .map(|((state, arg), pos)| ((swap(state), arg), pos))
+                .collect();
+            if *arg {
+                commands[0][0] = TMCmd {
+                    goto: swap(entry),
+                    read: Some(true),
+                    register_cmds: vec![],
+                };
+                definitions.insert((0, false), pos.clone());
+            }
+        }
+
+        let fill = self
+            .fill
+            .or(settings.fill.map(|(_pos, policy)| policy))
+            .unwrap_or_default();
+        let is_defined = |state: usize, arg: bool| definitions.contains_key(&(state as u32, arg));
+        // traps go to the first state without any lines, which loops forever
+        let trap = (0..)
+            .find(|&state| !is_defined(state, false) && !is_defined(state, true))
+            .expect("A program can't define every state.");
+        if fill == FillPolicy::Trap {
+            extend_vec_to(
+                &mut commands,
+                [TMCmd::default(), TMCmd::default()],
+                trap + 1,
+            );
+        }
+
+        let state_bits = largest_bit(commands.len().saturating_sub(1));
+        extend_vec_to(
+            &mut commands,
+            [TMCmd::default(), TMCmd::default()],
+            1 << state_bits,
+        );
+        for (state, pair) in commands.iter_mut().enumerate() {
+            for (arg, cmd) in [false, true].into_iter().zip(pair) {
+                if !is_defined(state, arg) {
+                    *cmd = fill.fill(state as u32, arg, trap as u32);
+                }
+            }
+        }
+        let (register_names, virtual_registers) =
+            allocate_registers(&mut commands, &header, &first_uses)?;
+
+        let mut sources = vec![[None, None]; commands.len()];
+        for ((state, arg), pos) in definitions {
+            sources[state as usize][arg as usize] = Some(pos);
+        }
+
+        let program = RieProgram {
+            commands,
+            register_names,
+            virtual_registers,
+            state_bits,
+            split_states,
+            sources,
+            fill,
+        };
+        Ok((program, warnings))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::rie::machine::{run_to_halt, Machine};
+
+    /// A program that leaves `0 true` and `1 true` undefined.
+    const PROGRAM: &str = "\tstate\targ\tgoto\tread\treg
+\t0\tfalse\t1\tfalse\t%
+\t1\tfalse\t1
+";
+
+    fn fill(policy: &str) -> RieProgram {
+        let (program, _warnings) =
+            RieProgram::parse(&format!("{PROGRAM}\tfill\t{policy}\n")).unwrap();
+        program
+    }
+
+    /// Where an undefined entry goes, and what it reads.
+    fn filled(program: &RieProgram, state: usize, arg: bool) -> (u32, Option<bool>) {
+        let cmd = &program.commands[state][arg as usize];
+        (cmd.goto, cmd.read)
+    }
+
+    #[test]
+    fn fills_with_halts() {
+        let program = fill("halt");
+        assert_eq!(program.fill(), FillPolicy::Halt);
+        assert!(program.commands[0][1].is_halt());
+        assert!(program.commands[1][1].is_halt());
+    }
+
+    #[test]
+    fn fills_with_loops() {
+        let program = fill("loop");
+        assert_eq!(filled(&program, 0, true), (0, Some(true)));
+        assert_eq!(filled(&program, 1, true), (1, Some(true)));
+    }
+
+    #[test]
+    fn fills_with_traps() {
+        // state 2 is the first state without lines, and it traps itself too
+        let program = fill("trap");
+        assert_eq!(program.state_bits(), 2);
+        for (state, arg) in [(0, true), (1, true), (2, false), (2, true)] {
+            assert_eq!(filled(&program, state, arg), (2, Some(false)));
+        }
+
+        // running into a mistake leaves the machine stuck there
+        let mut machine = Machine::new(&program);
+        machine.arg = true;
+        machine.run(&program, 100);
+        assert!(!machine.halted);
+        assert_eq!(machine.state, 2);
+    }
+
+    #[test]
+    fn assembler_overrides_the_fill_directive() {
+        let assembler = Assembler {
+            fill: Some(FillPolicy::Loop),
+            ..Assembler::new()
+        };
+        let source = format!("{PROGRAM}\tfill\ttrap\n");
+        let (program, _warnings) = assembler
+            .parse_str(&source, Arc::from(Path::new("test.rie")))
+            .unwrap();
+        assert_eq!(program.fill(), FillPolicy::Loop);
+        assert_eq!(filled(&program, 1, true), (1, Some(true)));
+    }
+
+    /// Runs a program until it halts, and returns its only register.
+    fn run(source: &str) -> String {
+        let (program, _warnings) = RieProgram::parse(source).unwrap();
+        run_to_halt(&program).registers[0].to_string()
+    }
+
+    // `main` pushes and then goes to state 0, which flips
+    const ENTRY: &str = "\tstate\targ\tgoto\tread\treg
+\t0\tfalse\t0\t\t%
+\tmain\tARG\t0\tfalse\t>
+";
+
+    #[test]
+    fn entry_starts_at_its_state() {
+        let source = ENTRY.replace("ARG", "false");
+        assert_eq!(run(&source), "[1]");
+        assert_eq!(run(&format!("{source}\tentry\tmain\n")), "0[1]");
+    }
+
+    #[test]
+    fn entry_can_start_with_arg_true() {
+        let source = format!("{}\tentry\tmain true\n", ENTRY.replace("ARG", "true"));
+        assert_eq!(run(&source), "0[1]");
+
+        // going to `main` with arg false still gets the fill policy, rather than the line for arg true
+        let source = "\tstate\targ\tgoto\tread\treg
+\t0\tfalse\tmain\tfalse\t%
+\tmain\ttrue\t0\tfalse\t>
+\tentry\tmain true
+";
+        assert_eq!(run(source), "0[1]");
+        // or its own line, if it has one
+        assert_eq!(run(&format!("{source}\tmain\tfalse\tmain\t\t>\n")), "01[0]");
+    }
+
+    #[test]
+    fn offsets_from_labels_keep_their_states() {
+        // `b` would be state 1 if `a+1` didn't keep it
+        let source = "\tstate\targ\tgoto\tread\treg
+\ta\tfalse\tnext\tfalse\t%
+\tb\tfalse\tb
+";
+        let (program, _warnings) = RieProgram::parse(source).unwrap();
+        assert_eq!(program.commands[0][0].goto, 1);
+        assert!(program.commands[1][0].is_halt());
+        assert_eq!(program.commands[2][0].goto, 2);
+    }
+
+    #[test]
+    fn offsets_from_labels_cant_land_on_labels() {
+        let source = "\tstate\targ\tgoto\tread\treg
+\tb\tfalse\tb
+\ta\tfalse\ta-1\tfalse\t%
+";
+        let result = RieProgram::parse(source);
+        assert!(matches!(result, Err(RieErr::OffsetOntoLabel(_, _, 0, label)) if label == "b"));
+    }
+}
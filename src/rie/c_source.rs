@@ -0,0 +1,272 @@
+use super::{register_cmd::RegisterCmd, rie_line::RieLine, RieProgram};
+use std::fmt::Write;
+
+/// Everything the generated program needs besides the commands themselves:
+/// registers as growable bit arrays, and printing them the way `rieasm run` does.
+const PRELUDE: &str = r#"#include <stdint.h>
+#include <stdio.h>
+#include <stdlib.h>
+#include <string.h>
+
+// An unbounded tape of bits, packed 8 to a byte.
+// Side 0 holds cells 0, 1, 2, ... and side 1 holds cells -1, -2, -3, ...
+typedef struct {
+    uint8_t *cells[2];
+    // how many cells of each side have been flipped at least once, counting from the middle
+    int64_t len[2];
+    // how many bytes of each side have been allocated
+    int64_t cap[2];
+    int64_t head;
+} Tape;
+
+static inline int get_bit(const Tape *tape, int64_t pos) {
+    int side = pos < 0;
+    int64_t index = side ? -pos - 1 : pos;
+    return index < tape->len[side] && (tape->cells[side][index / 8] >> (index % 8) & 1);
+}
+
+static inline int read_bit(const Tape *tape) {
+    return get_bit(tape, tape->head);
+}
+
+static inline void flip(Tape *tape) {
+    int side = tape->head < 0;
+    int64_t index = side ? -tape->head - 1 : tape->head;
+    if (index >= tape->len[side]) {
+        int64_t bytes = index / 8 + 1;
+        if (bytes > tape->cap[side]) {
+            int64_t cap = tape->cap[side] ? tape->cap[side] : 16;
+            while (cap < bytes) cap *= 2;
+            uint8_t *cells = realloc(tape->cells[side], cap);
+            if (!cells) {
+                fputs("Ran out of memory for the registers.\n", stderr);
+                exit(1);
+            }
+            memset(cells + tape->cap[side], 0, cap - tape->cap[side]);
+            tape->cells[side] = cells;
+            tape->cap[side] = cap;
+        }
+        tape->len[side] = index + 1;
+    }
+    tape->cells[side][index / 8] ^= 1 << (index % 8);
+}
+
+static inline void show(const char *name, const Tape *tape) {
+    int64_t low = -tape->len[1];
+    int64_t high = tape->len[0] - 1;
+    if (tape->head < low) low = tape->head;
+    if (tape->head > high) high = tape->head;
+    printf("    %s: ", name);
+    for (int64_t pos = low; pos <= high; pos++) {
+        if (pos == tape->head) printf("[%d]", get_bit(tape, pos));
+        else printf("%d", get_bit(tape, pos));
+    }
+    putchar('\n');
+}
+"#;
+
+/// Writes `s` as a C string literal.
+fn c_string(s: &str) -> String {
+    let mut out = String::from("\"");
+    for c in s.chars() {
+        match c {
+            '"' | '\\' => {
+                out.push('\\');
+                out.push(c);
+            }
+            '\n' => out.push_str("\\n"),
+            c => out.push(c),
+        }
+    }
+    out.push('"');
+    out
+}
+
+impl RieProgram {
+    /// A standalone C program that runs the program natively,
+    /// with one `case` for every demux address (`state << 1 | arg`)
+    /// and every register as a growable bit array.
+    ///
+    /// When run, it prints the same report as `rieasm run`.
+    /// It takes the most steps to run as its only argument, defaulting to `max_steps`.
+    pub fn to_c(&self, max_steps: u64) -> String {
+        use RegisterCmd::*;
+
+        let registers = self.register_count();
+        let state_digits = self.state_digits();
+
+        let mut out = String::new();
+        let o = &mut out;
+        writeln!(o, "// generated by rieasm").unwrap();
+        writeln!(
+            o,
+            "// A Read-If/Else machine with {} state bits and {} registers, running the program.",
+            self.state_bits, registers
+        )
+        .unwrap();
+        writeln!(
+            o,
+            "// Compile it with `cc -O2 program.c -o program` and run it with `./program [steps]`."
+        )
+        .unwrap();
+        writeln!(o, "// It prints the same report as `rieasm run`.").unwrap();
+        o.push_str(PRELUDE);
+        writeln!(o).unwrap();
+
+        writeln!(o, "int main(int argc, char **argv) {{").unwrap();
+        writeln!(
+            o,
+            "    uint64_t max_steps = argc > 1 ? strtoull(argv[1], NULL, 10) : {max_steps}ULL;"
+        )
+        .unwrap();
+        // C has no empty arrays
+        writeln!(o, "    static Tape tapes[{}];", registers.max(1)).unwrap();
+        writeln!(o, "    uint32_t state = 0;").unwrap();
+        writeln!(o, "    int arg = 0;").unwrap();
+        writeln!(o, "    int halted = 0;").unwrap();
+        writeln!(o, "    uint64_t steps = 0;").unwrap();
+        writeln!(o).unwrap();
+        writeln!(o, "    while (!halted && steps < max_steps) {{").unwrap();
+        writeln!(o, "        steps++;").unwrap();
+        writeln!(o, "        switch (state << 1 | arg) {{").unwrap();
+        for (state, pair) in self.commands.iter().enumerate() {
+            for (arg, cmd) in pair.iter().enumerate() {
+                let line = RieLine::to_string(
+                    state_digits,
+                    &self.register_names,
+                    state as u32,
+                    arg == 1,
+                    cmd,
+                );
+                writeln!(o, "        case {}: // {line}", state << 1 | arg).unwrap();
+                let mut read = cmd.read.map(|bit| (bit as u8).to_string());
+                for (register, &register_cmd) in cmd.register_cmds.iter().enumerate() {
+                    let tape = format!("&tapes[{register}]");
+                    match register_cmd {
+                        Noop => {}
+                        Push => writeln!(o, "            tapes[{register}].head++;").unwrap(),
+                        Pull => writeln!(o, "            tapes[{register}].head--;").unwrap(),
+                        Flip => writeln!(o, "            flip({tape});").unwrap(),
+                        FlipPull => {
+                            writeln!(o, "            flip({tape});").unwrap();
+                            writeln!(o, "            tapes[{register}].head--;").unwrap();
+                        }
+                        Read => read = Some(format!("read_bit({tape})")),
+                    }
+                }
+                writeln!(o, "            state = {};", cmd.goto).unwrap();
+                match read {
+                    Some(bit) => writeln!(o, "            arg = {bit};").unwrap(),
+                    None => writeln!(o, "            halted = 1;").unwrap(),
+                }
+                writeln!(o, "            break;").unwrap();
+            }
+        }
+        writeln!(o, "        }}").unwrap();
+        writeln!(o, "    }}").unwrap();
+        writeln!(o).unwrap();
+
+        writeln!(o, "    if (halted) {{").unwrap();
+        writeln!(
+            o,
+            "        printf(\"Halted after %llu steps, at state %u.\\n\", (unsigned long long)steps, state);"
+        )
+        .unwrap();
+        writeln!(o, "    }} else {{").unwrap();
+        writeln!(
+            o,
+            "        printf(\"Still running after %llu steps, at state %u with arg %s.\\n\","
+        )
+        .unwrap();
+        writeln!(
+            o,
+            "            (unsigned long long)steps, state, arg ? \"true\" : \"false\");"
+        )
+        .unwrap();
+        writeln!(o, "    }}").unwrap();
+        for register in 0..registers {
+            let name = c_string(&self.register_names.describe(register));
+            writeln!(o, "    show({name}, &tapes[{register}]);").unwrap();
+        }
+        writeln!(o, "    return 0;").unwrap();
+        writeln!(o, "}}").unwrap();
+        out
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::rie::machine::Machine;
+    use std::{
+        env, fs,
+        process::{self, Command},
+    };
+
+    // loops on `x` until it reads a 1, which it never does
+    const FOREVER: &str = "\
+\tstate\targ\tgoto\tread\treg:x\treg:y
+\t0\tfalse\t0\t\t<?\t%>
+";
+
+    /// Compiles the C source with `cc` and runs it for at most `max_steps`,
+    /// or returns `None` if there's no `cc` to compile it with.
+    fn run_c(program: &RieProgram, name: &str, max_steps: u64) -> Option<String> {
+        let dir = env::temp_dir().join(format!("rieasm-c-{}-{name}", process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let (source, binary) = (dir.join("program.c"), dir.join("program"));
+        fs::write(&source, program.to_c(max_steps)).unwrap();
+        let Ok(status) = Command::new("cc")
+            .arg("-O2")
+            .arg(&source)
+            .arg("-o")
+            .arg(&binary)
+            .status()
+        else {
+            eprintln!("Skipping the C test, since there's no cc.");
+            fs::remove_dir_all(&dir).unwrap();
+            return None;
+        };
+        assert!(status.success(), "The C source didn't compile.");
+        let output = Command::new(&binary).output().unwrap();
+        fs::remove_dir_all(&dir).unwrap();
+        assert!(output.status.success());
+        Some(String::from_utf8(output.stdout).unwrap())
+    }
+
+    fn runs_the_same(program: &RieProgram, name: &str, max_steps: u64) {
+        let mut machine = Machine::new(program);
+        machine.run(program, max_steps);
+        if let Some(report) = run_c(program, name, max_steps) {
+            assert_eq!(report, machine.report(program));
+        }
+    }
+
+    #[test]
+    fn has_a_case_for_every_address() {
+        let (program, _warnings) = RieProgram::parse(include_str!("../../program.rie")).unwrap();
+        let source = program.to_c(100);
+        for (state, pair) in program.commands.iter().enumerate() {
+            for arg in 0..pair.len() {
+                assert!(source.contains(&format!("\n        case {}: //", state << 1 | arg)));
+            }
+        }
+    }
+
+    #[test]
+    fn reports_like_run() {
+        let (program, _warnings) = RieProgram::parse(FOREVER).unwrap();
+        let source = program.to_c(100);
+        assert!(source.contains("printf(\"Halted after %llu steps, at state %u.\\n\""));
+        assert!(source.contains("\"Still running after %llu steps, at state %u with arg %s.\\n\""));
+        assert!(source.contains("show(\"y\", &tapes[1]);"));
+    }
+
+    #[test]
+    fn runs_like_machine() {
+        let (program, _warnings) = RieProgram::parse(include_str!("../../program.rie")).unwrap();
+        runs_the_same(&program, "program", 1_000_000);
+        let (forever, _warnings) = RieProgram::parse(FOREVER).unwrap();
+        runs_the_same(&forever, "forever", 1000);
+    }
+}
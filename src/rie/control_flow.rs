@@ -0,0 +1,749 @@
+use super::{
+    header::RegisterNames,
+    label::StateRef,
+    register_cmd::{BadRegisterCmd, RegisterCmd},
+    rie_line::RieLine,
+    source::SourcePos,
+    RieErr,
+};
+use std::collections::{BTreeSet, HashSet};
+use thiserror::Error;
+
+#[derive(Error, Debug)]
+pub enum ControlFlowErr {
+    #[error(
+        "A routine needs a state to start at.\n\
+        Example: routine main {{"
+    )]
+    ExpectedRoutineName,
+
+    #[error("{0:?} can't be the name of a routine: {1}")]
+    BadRoutineName(String, String),
+
+    #[error("This {0} block is never closed with a `}}`.")]
+    Unclosed(&'static str),
+
+    #[error("Found an `else` after a {0} block. Only `if` blocks can have an `else`.")]
+    StrayElse(&'static str),
+
+    #[error("`break` can only be used inside a `while` or `loop` block.")]
+    BreakOutsideLoop,
+
+    #[error(
+        "`{0}` needs a condition that reads exactly one register, followed by `{{`.\n\
+        Example: {0} prog? {{"
+    )]
+    BadCondition(&'static str),
+
+    #[error(
+        "`loop` doesn't take a condition.\n\
+        Example: loop {{"
+    )]
+    BadLoop,
+
+    #[error(
+        "`goto` needs exactly one state to go to.\n\
+        Example: goto main"
+    )]
+    BadGoto,
+
+    #[error("Could not parse the state {0:?} in a `goto`.")]
+    BadTarget(String),
+
+    #[error(
+        "{0:?} is not a register command.\n\
+        Commands in routines look like `%@bit`, `>@2`, or `prog?`."
+    )]
+    BadOp(String),
+
+    #[error("Invalid command in {0:?}: {1}")]
+    BadCommand(String, BadRegisterCmd),
+
+    #[error("There is no register named {0:?}.")]
+    BadRegister(String),
+
+    #[error("{0} reads before the end of its commands. A read has to be the last command.")]
+    EarlyRead(String),
+
+    #[error("Only the condition of an `if` or `while` can read a register.")]
+    UnusedRead,
+}
+
+/// A statement inside of a routine.
+enum Stmt {
+    Ops(SourcePos, Vec<RegisterCmd>),
+    /// `cond` is the commands of every step of the condition, the last of which reads.
+    If {
+        pos: SourcePos,
+        cond: Vec<Vec<RegisterCmd>>,
+        then: Vec<Stmt>,
+        otherwise: Vec<Stmt>,
+    },
+    While {
+        pos: SourcePos,
+        cond: Vec<Vec<RegisterCmd>>,
+        body: Vec<Stmt>,
+    },
+    Loop(Vec<Stmt>),
+    Break(SourcePos),
+    Goto(StateRef),
+    Halt,
+}
+
+/// How a block of statements ended.
+enum Closer {
+    Close,
+    Else,
+}
+
+pub const ROUTINE: &str = "routine";
+const OPEN: &str = "{";
+const CLOSE: &str = "}";
+
+/// The words of a line of structured code, without its comment.
+fn words(line: &str) -> Vec<&str> {
+    let code = line.split("--").next().unwrap_or("");
+    code.split_whitespace().collect()
+}
+
+/// Whether a line starts a routine, as in `routine main {`.
+pub fn is_routine_start(line: &str) -> bool {
+    let words = words(line);
+    words.first() == Some(&ROUTINE) && words.last() == Some(&OPEN)
+}
+
+/// Whether a line of a routine closes a block, and whether it opens one.
+/// `} else {` does both.
+pub fn nesting(line: &str) -> (bool, bool) {
+    let words = words(line);
+    (words.first() == Some(&CLOSE), words.last() == Some(&OPEN))
+}
+
+struct Parser<'a, I: Iterator<Item = (SourcePos, String)>> {
+    lines: &'a mut I,
+    register_names: &'a RegisterNames,
+}
+
+impl<I: Iterator<Item = (SourcePos, String)>> Parser<'_, I> {
+    /// Turns words like `%@bit` and `prog?` into a command for every register column.
+    /// Words like `>>@mem` take more than one step, so this returns the commands of every step.
+    fn ops(&self, words: &[&str]) -> Result<Vec<Vec<RegisterCmd>>, ControlFlowErr> {
+        use ControlFlowErr::*;
+
+        let mut sequences = vec![vec![]; self.register_names.0.len()];
+        for &word in words {
+            let (token, target) = match word.split_once(RegisterCmd::ADDRESS_SEPARATOR) {
+                Some(split) => split,
+                // `prog?` is short for `?@prog`
+                None => match word.strip_suffix('?') {
+                    Some(target) if !target.is_empty() => ("?", target),
+                    _ => return Err(BadOp(word.to_owned())),
+                },
+            };
+            let register = self
+                .register_names
+                .find(target)
+                .ok_or_else(|| BadRegister(target.to_owned()))?;
+            let cmds =
+                RegisterCmd::parse_sequence(token).map_err(|e| BadCommand(word.to_owned(), e))?;
+            // later words for the same register run after earlier ones, so `>@mem mem?` pushes and then reads
+            sequences[register].extend(cmds);
+        }
+        for (register, sequence) in sequences.iter().enumerate() {
+            if sequence
+                .split_last()
+                .is_some_and(|(_last, before)| before.contains(&RegisterCmd::Read))
+            {
+                return Err(EarlyRead(self.register_names.describe(register)));
+            }
+        }
+        Ok(RegisterCmd::steps(&sequences))
+    }
+
+    /// Reads the condition of an `if` or `while`, which must end in `{`.
+    fn cond(
+        &self,
+        keyword: &'static str,
+        words: &[&str],
+    ) -> Result<Vec<Vec<RegisterCmd>>, ControlFlowErr> {
+        let bad = || ControlFlowErr::BadCondition(keyword);
+        let words = words.strip_suffix(&[OPEN]).ok_or_else(bad)?;
+        let cond = self.ops(words)?;
+        let reads = cond
+            .iter()
+            .flatten()
+            .filter(|&&cmd| cmd == RegisterCmd::Read)
+            .count();
+        (reads == 1).then_some(cond).ok_or_else(bad)
+    }
+
+    /// Reads statements until the `}` that closes the block opened at `open`.
+    fn block(
+        &mut self,
+        keyword: &'static str,
+        open: &SourcePos,
+    ) -> Result<(Vec<Stmt>, Closer), RieErr> {
+        use ControlFlowErr::*;
+
+        let mut stmts = vec![];
+        while let Some((pos, line)) = self.lines.next() {
+            let bad = |e| RieErr::BadControlFlow(pos.clone(), e);
+            let words = words(&line);
+            let stmt = match words.as_slice() {
+                [] => continue,
+                [CLOSE] => return Ok((stmts, Closer::Close)),
+                [CLOSE, "else", OPEN] => return Ok((stmts, Closer::Else)),
+                ["if", cond @ ..] => {
+                    let cond = self.cond("if", cond).map_err(bad)?;
+                    let (then, closer) = self.block("if", &pos)?;
+                    let otherwise = match closer {
+                        Closer::Close => vec![],
+                        Closer::Else => self.closed_block("else", &pos)?,
+                    };
+                    Stmt::If {
+                        pos,
+                        cond,
+                        then,
+                        otherwise,
+                    }
+                }
+                ["while", cond @ ..] => {
+                    let cond = self.cond("while", cond).map_err(bad)?;
+                    let body = self.closed_block("while", &pos)?;
+                    Stmt::While { pos, cond, body }
+                }
+                ["loop", OPEN] => Stmt::Loop(self.closed_block("loop", &pos)?),
+                ["loop", ..] => return Err(bad(BadLoop)),
+                ["break"] => Stmt::Break(pos),
+                ["goto", target] => Stmt::Goto(
+                    target
+                        .parse()
+                        .map_err(|_| bad(BadTarget(target.to_string())))?,
+                ),
+                ["goto", ..] => return Err(bad(BadGoto)),
+                ["halt"] => Stmt::Halt,
+                ops => {
+                    let steps = self.ops(ops).map_err(bad)?;
+                    if steps.iter().flatten().any(|&cmd| cmd == RegisterCmd::Read) {
+                        return Err(bad(UnusedRead));
+                    }
+                    let (last, before) = steps.split_last().expect("Every statement takes a step.");
+                    for step in before {
+                        stmts.push(Stmt::Ops(pos.clone(), step.clone()));
+                    }
+                    Stmt::Ops(pos, last.clone())
+                }
+            };
+            stmts.push(stmt);
+        }
+        Err(RieErr::BadControlFlow(open.clone(), Unclosed(keyword)))
+    }
+
+    /// Reads a block that can't be followed by an `else`.
+    fn closed_block(
+        &mut self,
+        keyword: &'static str,
+        open: &SourcePos,
+    ) -> Result<Vec<Stmt>, RieErr> {
+        match self.block(keyword, open)? {
+            (stmts, Closer::Close) => Ok(stmts),
+            (_stmts, Closer::Else) => Err(RieErr::BadControlFlow(
+                open.clone(),
+                ControlFlowErr::StrayElse(keyword),
+            )),
+        }
+    }
+}
+
+/// Where the machine goes after a command.
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Target {
+    Node(usize),
+    Goto(StateRef),
+    Halt,
+}
+
+enum Next {
+    /// Go on with arg false.
+    Continue(Target),
+    /// Go one way or the other depending on the register that was read.
+    Branch { then: Target, otherwise: Target },
+}
+
+/// One command of a lowered routine.
+struct Node {
+    pos: SourcePos,
+    register_cmds: Vec<RegisterCmd>,
+    next: Next,
+}
+
+enum Slot {
+    /// A node whose command isn't known yet, because it's the start of a loop.
+    Pending,
+    /// Stands for wherever another target goes.
+    Alias(Target),
+    Node(Node),
+}
+
+/// A state of the lowered routine.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+enum Emit {
+    /// The state of a node's own command.
+    Node(usize),
+    /// The state after a node's read, holding the first command of each branch.
+    Split(usize),
+}
+
+/// Turns the statements of a routine into commands.
+struct Lowering {
+    slots: Vec<Slot>,
+    column_count: usize,
+}
+
+impl Lowering {
+    fn push(&mut self, slot: Slot) -> usize {
+        self.slots.push(slot);
+        self.slots.len() - 1
+    }
+
+    /// Lowers `stmts`, which go on to `next` when they're done,
+    /// and returns where to go to run them.
+    fn block(
+        &mut self,
+        stmts: &[Stmt],
+        next: Target,
+        brk: Option<&Target>,
+    ) -> Result<Target, RieErr> {
+        let mut next = next;
+        for stmt in stmts.iter().rev() {
+            next = self.stmt(stmt, next, brk)?;
+        }
+        Ok(next)
+    }
+
+    fn stmt(&mut self, stmt: &Stmt, next: Target, brk: Option<&Target>) -> Result<Target, RieErr> {
+        let node = |pos: &SourcePos, register_cmds: &Vec<RegisterCmd>, next| {
+            Slot::Node(Node {
+                pos: pos.clone(),
+                register_cmds: register_cmds.clone(),
+                next,
+            })
+        };
+        let target = match stmt {
+            Stmt::Ops(pos, ops) => Target::Node(self.push(node(pos, ops, Next::Continue(next)))),
+            Stmt::If {
+                pos,
+                cond,
+                then,
+                otherwise,
+            } => {
+                let then = self.block(then, next.clone(), brk)?;
+                let otherwise = self.block(otherwise, next, brk)?;
+                self.cond(pos, cond, then, otherwise)
+            }
+            Stmt::While { pos, cond, body } => {
+                let head = self.push(Slot::Pending);
+                let then = self.block(body, Target::Node(head), Some(&next))?;
+                let start = self.cond(pos, cond, then, next);
+                self.slots[head] = Slot::Alias(start);
+                Target::Node(head)
+            }
+            Stmt::Loop(body) => {
+                let head = self.push(Slot::Pending);
+                let start = self.block(body, Target::Node(head), Some(&next))?;
+                self.slots[head] = Slot::Alias(start);
+                Target::Node(head)
+            }
+            Stmt::Break(pos) => brk.cloned().ok_or_else(|| {
+                RieErr::BadControlFlow(pos.clone(), ControlFlowErr::BreakOutsideLoop)
+            })?,
+            Stmt::Goto(state) => Target::Goto(state.clone()),
+            Stmt::Halt => Target::Halt,
+        };
+        Ok(target)
+    }
+
+    /// Lowers the steps of a condition, which go one way or the other after the last step reads.
+    fn cond(
+        &mut self,
+        pos: &SourcePos,
+        cond: &[Vec<RegisterCmd>],
+        then: Target,
+        otherwise: Target,
+    ) -> Target {
+        let (last, before) = cond.split_last().expect("Every condition takes a step.");
+        let mut start = Target::Node(self.push(Slot::Node(Node {
+            pos: pos.clone(),
+            register_cmds: last.clone(),
+            next: Next::Branch { then, otherwise },
+        })));
+        for step in before.iter().rev() {
+            start = Target::Node(self.push(Slot::Node(Node {
+                pos: pos.clone(),
+                register_cmds: step.clone(),
+                next: Next::Continue(start),
+            })));
+        }
+        start
+    }
+
+    /// Follows aliases until reaching a real target.
+    /// Loops that do nothing become a node that waits forever.
+    fn resolve(&mut self, target: &Target, pos: &SourcePos) -> Target {
+        let mut seen = HashSet::new();
+        let mut target = target.clone();
+        while let Target::Node(id) = target {
+            match &self.slots[id] {
+                Slot::Alias(next) if seen.insert(id) => target = next.clone(),
+                Slot::Alias(_) => {
+                    self.slots[id] = Slot::Node(Node {
+                        pos: pos.clone(),
+                        register_cmds: vec![RegisterCmd::Noop; self.column_count],
+                        next: Next::Continue(Target::Node(id)),
+                    });
+                    return Target::Node(id);
+                }
+                _ => return Target::Node(id),
+            }
+        }
+        target
+    }
+
+    /// Replaces every alias in every node with what it stands for.
+    fn resolve_all(&mut self) {
+        for id in 0..self.slots.len() {
+            let Slot::Node(node) = &self.slots[id] else {
+                continue;
+            };
+            let pos = node.pos.clone();
+            let next = match &node.next {
+                Next::Continue(target) => Next::Continue(target.clone()),
+                Next::Branch { then, otherwise } => Next::Branch {
+                    then: then.clone(),
+                    otherwise: otherwise.clone(),
+                },
+            };
+            let next = match next {
+                Next::Continue(target) => Next::Continue(self.resolve(&target, &pos)),
+                Next::Branch { then, otherwise } => Next::Branch {
+                    then: self.resolve(&then, &pos),
+                    otherwise: self.resolve(&otherwise, &pos),
+                },
+            };
+            if let Slot::Node(node) = &mut self.slots[id] {
+                node.next = next;
+            }
+        }
+    }
+
+    fn node(&self, id: usize) -> &Node {
+        match &self.slots[id] {
+            Slot::Node(node) => node,
+            _ => unreachable!("Every target was resolved to a node."),
+        }
+    }
+}
+
+/// The lines of one routine, with states named after the routine.
+struct Emitter<'a> {
+    lowering: &'a Lowering,
+    start: &'a StateRef,
+    base: String,
+    entry: Option<usize>,
+}
+
+impl Emitter<'_> {
+    fn label(&self, emit: Emit) -> StateRef {
+        match emit {
+            Emit::Node(id) if Some(id) == self.entry => self.start.clone(),
+            Emit::Node(id) => StateRef::Label(format!("{}#{id}", self.base)),
+            Emit::Split(id) => StateRef::Label(format!("{}#{id}.split", self.base)),
+        }
+    }
+
+    /// The command that starts going to `target`, and the state it goes to next, if any.
+    fn cmd(
+        &self,
+        target: &Target,
+        pos: &SourcePos,
+        state: &StateRef,
+        arg: bool,
+    ) -> (RieLine, Option<Emit>, SourcePos) {
+        let column_count = self.lowering.column_count;
+        let line = |goto, read, register_cmds| RieLine {
+            state: state.clone(),
+            arg,
+            goto,
+            read,
+            register_cmds,
+        };
+        match target {
+            Target::Node(id) => {
+                let node = self.lowering.node(*id);
+                let (goto, read, emit) = match &node.next {
+                    Next::Continue(Target::Node(next)) => {
+                        let emit = Emit::Node(*next);
+                        (Some(self.label(emit)), Some(false), Some(emit))
+                    }
+                    Next::Continue(Target::Goto(state)) => (Some(state.clone()), Some(false), None),
+                    Next::Continue(Target::Halt) => (None, None, None),
+                    Next::Branch { .. } => {
+                        let emit = Emit::Split(*id);
+                        (Some(self.label(emit)), None, Some(emit))
+                    }
+                };
+                (
+                    line(goto, read, node.register_cmds.clone()),
+                    emit,
+                    node.pos.clone(),
+                )
+            }
+            Target::Goto(goto) => (
+                line(
+                    Some(goto.clone()),
+                    Some(false),
+                    vec![RegisterCmd::Noop; column_count],
+                ),
+                None,
+                pos.clone(),
+            ),
+            Target::Halt => (
+                line(None, None, vec![RegisterCmd::Noop; column_count]),
+                None,
+                pos.clone(),
+            ),
+        }
+    }
+
+    /// Every line of the routine, starting from `entry` and following every command.
+    fn lines(&self, start: &Target, pos: &SourcePos) -> Vec<(SourcePos, RieLine)> {
+        let mut lines = vec![];
+        let mut emitted = BTreeSet::new();
+        let mut queue = vec![];
+
+        // the routine can be entered with either arg
+        for arg in [false, true] {
+            let (line, next, pos) = self.cmd(start, pos, self.start, arg);
+            lines.push((pos, line));
+            queue.extend(next);
+        }
+        if let Some(entry) = self.entry {
+            emitted.insert(Emit::Node(entry));
+        }
+
+        while let Some(emit) = queue.pop() {
+            if !emitted.insert(emit) {
+                continue;
+            }
+            let state = self.label(emit);
+            let branches = match emit {
+                // everything else goes on with arg false
+                Emit::Node(id) => vec![(false, Target::Node(id))],
+                Emit::Split(id) => match &self.lowering.node(id).next {
+                    Next::Branch { then, otherwise } => {
+                        vec![(false, otherwise.clone()), (true, then.clone())]
+                    }
+                    Next::Continue(_) => unreachable!("Only branches have splits."),
+                },
+            };
+            for (arg, target) in branches {
+                let (line, next, pos) = self.cmd(&target, pos, &state, arg);
+                lines.push((pos, line));
+                queue.extend(next);
+            }
+        }
+        lines
+    }
+}
+
+/// Lowers a routine whose first line, `routine <start> {`, is at `pos`.
+fn routine(
+    pos: SourcePos,
+    line: &str,
+    lines: &mut impl Iterator<Item = (SourcePos, String)>,
+    register_names: &RegisterNames,
+) -> Result<Vec<(SourcePos, RieLine)>, RieErr> {
+    use ControlFlowErr::*;
+
+    let start = match words(line).as_slice() {
+        [ROUTINE, start, OPEN] => start.parse::<StateRef>().map_err(|e| {
+            RieErr::BadControlFlow(
+                pos.clone(),
+                BadRoutineName(start.to_string(), e.to_string()),
+            )
+        })?,
+        _ => return Err(RieErr::BadControlFlow(pos, ExpectedRoutineName)),
+    };
+    let base = match &start {
+        StateRef::Number(number) => format!("state{number}"),
+        StateRef::Label(label) => label.clone(),
+        StateRef::Offset(..) => {
+            return Err(RieErr::BadControlFlow(
+                pos,
+                BadRoutineName(
+                    start.to_string(),
+                    "Routines have to start at a state number or a label.".to_owned(),
+                ),
+            ))
+        }
+    };
+
+    let mut parser = Parser {
+        lines,
+        register_names,
+    };
+    let stmts = parser.closed_block(ROUTINE, &pos)?;
+
+    let mut lowering = Lowering {
+        slots: vec![],
+        column_count: register_names.0.len(),
+    };
+    let entry = lowering.block(&stmts, Target::Halt, None)?;
+    lowering.resolve_all();
+    let entry = lowering.resolve(&entry, &pos);
+
+    let emitter = Emitter {
+        lowering: &lowering,
+        start: &start,
+        base,
+        entry: match entry {
+            Target::Node(id) => Some(id),
+            _ => None,
+        },
+    };
+    Ok(emitter.lines(&entry, &pos))
+}
+
+/// Parses every line of code, lowering routines into plain lines along the way.
+/// Also returns how many states were added to split up lines that take more than one step.
+pub fn lower(
+    code: Vec<(SourcePos, String)>,
+    register_names: &RegisterNames,
+) -> Result<(Vec<(SourcePos, RieLine)>, usize), RieErr> {
+    let mut lines = vec![];
+    let mut split_states = 0;
+    let mut code = code.into_iter();
+    while let Some((pos, line)) = code.next() {
+        if is_routine_start(&line) {
+            lines.extend(routine(pos, &line, &mut code, register_names)?);
+            continue;
+        }
+        let (steps, step_states) =
+            RieLine::parse(&line, register_names).map_err(|e| RieErr::BadLine(pos.clone(), e))?;
+        split_states += step_states;
+        lines.extend(steps.into_iter().map(|step| (pos.clone(), step)));
+    }
+    Ok((lines, split_states))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::ControlFlowErr;
+    use crate::rie::{machine::run_to_halt, RieErr, RieProgram};
+
+    /// Runs a routine over the registers `a`, `b` and `c`, and returns the registers once it halts.
+    fn run(routine: &str) -> Vec<String> {
+        let source = format!("\tstate\targ\tgoto\tread\treg:a\treg:b\treg:c\n{routine}");
+        let (program, _warnings) = RieProgram::parse(&source).unwrap();
+        run_to_halt(&program)
+            .registers
+            .iter()
+            .map(|tape| tape.to_string())
+            .collect()
+    }
+
+    fn parse_err(routine: &str) -> ControlFlowErr {
+        let source = format!("\tstate\targ\tgoto\tread\treg:a\n{routine}");
+        match RieProgram::parse(&source) {
+            Err(RieErr::BadControlFlow(_pos, e)) => e,
+            result => panic!(
+                "Expected a control flow error, got {:?}",
+                result.map(|_| ())
+            ),
+        }
+    }
+
+    // `SET` is replaced with the commands that set up `a`
+    const IF_ELSE: &str = "\troutine 0 {
+\t\tSET
+\t\tif a? {
+\t\t\t%@b
+\t\t} else {
+\t\t\t%@c
+\t\t}
+\t}
+";
+
+    #[test]
+    fn if_takes_one_branch() {
+        assert_eq!(run(&IF_ELSE.replace("SET", "%@a")), ["[1]", "[1]", "[0]"]);
+        assert_eq!(run(&IF_ELSE.replace("SET", "")), ["[0]", "[0]", "[1]"]);
+    }
+
+    #[test]
+    fn while_loops_until_the_read_is_false() {
+        // sets 3 cells of `a`, then moves them to `b`
+        let routine = "\troutine 0 {
+\t\t%@a
+\t\t>@a
+\t\t%@a
+\t\t>@a
+\t\t%@a
+\t\t<@a
+\t\t<@a
+\t\twhile a? {
+\t\t\t%@a
+\t\t\t>@a
+\t\t\t%@b
+\t\t\t>@b
+\t\t}
+\t}
+";
+        assert_eq!(run(routine), ["000[0]", "111[0]", "[0]"]);
+    }
+
+    #[test]
+    fn break_leaves_the_loop() {
+        // counts the unset cells of `a` before a set one into `b`
+        let routine = "\troutine 0 {
+\t\t>@a
+\t\t>@a
+\t\t%@a
+\t\t<@a
+\t\t<@a
+\t\tloop {
+\t\t\tif a? {
+\t\t\t\tbreak
+\t\t\t}
+\t\t\t>@a %@b
+\t\t\t>@b
+\t\t}
+\t\t%@c
+\t}
+";
+        assert_eq!(run(routine), ["00[1]", "11[0]", "[1]"]);
+    }
+
+    #[test]
+    fn goto_leaves_the_routine() {
+        let routine = "\troutine 0 {
+\t\tgoto done
+\t\t%@b
+\t}
+\tdone\tfalse\tdone\t\t\t\t%
+";
+        assert_eq!(run(routine), ["[0]", "[0]", "[1]"]);
+    }
+
+    #[test]
+    fn rejects_bad_blocks() {
+        let err = parse_err("\troutine 0 {\n\t\tbreak\n\t}\n");
+        assert!(matches!(err, ControlFlowErr::BreakOutsideLoop));
+        let err = parse_err("\troutine 0 {\n\t\twhile a? {\n\t\t}\n");
+        assert!(matches!(err, ControlFlowErr::Unclosed("routine")));
+        let err = parse_err("\troutine 0 {\n\t\tloop {\n\t\t} else {\n\t\t}\n\t}\n");
+        assert!(matches!(err, ControlFlowErr::StrayElse("loop")));
+        let err = parse_err("\troutine 0 {\n\t\tif %@a {\n\t\t}\n\t}\n");
+        assert!(matches!(err, ControlFlowErr::BadCondition("if")));
+    }
+}
@@ -0,0 +1,251 @@
+use super::{
+    assembler::FillPolicy,
+    label::{BadStateRef, StateRef},
+    rie_line::ArgPattern,
+    source::SourcePos,
+};
+use thiserror::Error;
+
+#[derive(Error, Debug)]
+pub enum DirectiveErr {
+    #[error("Unclosed quote in {0:?}.")]
+    UnclosedQuote(String),
+
+    #[error(
+        "The {0} directive needs a path in quotes.\n\
+        Example: {0} \"lib/register_demo.rie\""
+    )]
+    ExpectedPath(&'static str),
+
+    #[error("The {0} directive doesn't take {1:?}.")]
+    TooManyArgs(&'static str, String),
+
+    #[error(
+        "The {0} directive needs the name of a macro.\n\
+        Example: {0} register_demo"
+    )]
+    ExpectedName(&'static str),
+
+    #[error(
+        "The {0} directive needs to know what undefined entries should do: one of {:?}.\n\
+        Example: {0} trap",
+        FillPolicy::POLICIES.map(|(name, _policy)| name),
+    )]
+    ExpectedFillPolicy(&'static str),
+
+    #[error(
+        "Unknown fill policy: {0:?}\n\
+        Valid policies: {:?}",
+        FillPolicy::POLICIES.map(|(name, _policy)| name),
+    )]
+    UnknownFillPolicy(String),
+
+    #[error(
+        "The {0} directive needs a state to start at, and optionally the arg to start with.\n\
+        Example: {0} main true"
+    )]
+    ExpectedState(&'static str),
+
+    #[error("The {0} directive can't start at {1:?}: {2}")]
+    BadState(&'static str, String, String),
+
+    #[error(
+        "The {0} directive can't start with an arg of {1:?}.\n\
+        Args must be either true or false."
+    )]
+    BadArg(&'static str, String),
+
+    #[error("The {0} directive was already used at {1}, and can only be used once.")]
+    Repeated(&'static str, SourcePos),
+}
+
+/// A line of code that tells the assembler to do something, instead of specifying an instruction.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Directive {
+    /// Pastes the code of another `.rie` file in place of this line.
+    Include(String),
+    /// Starts the definition of a macro, which lasts until [`Directive::End`].
+    Macro {
+        name: String,
+        params: Vec<String>,
+    },
+    End,
+    /// Pastes in the code of a macro.
+    Use {
+        name: String,
+        args: Vec<String>,
+    },
+    /// Chooses what entries that no line defines should do.
+    Fill(FillPolicy),
+    /// Chooses the state and arg the program starts at.
+    Entry(StateRef, bool),
+}
+
+/// A word in a directive, remembering whether it was quoted.
+struct Word {
+    text: String,
+    quoted: bool,
+}
+
+/// Splits a line into words separated by whitespace, stopping at a `--` comment.
+/// Words in double quotes may contain whitespace.
+fn split_words(line: &str) -> Result<Vec<Word>, DirectiveErr> {
+    let mut words = vec![];
+    let mut rest = line.trim();
+    while !rest.is_empty() && !rest.starts_with("--") {
+        if let Some(quoted) = rest.strip_prefix('"') {
+            let end = quoted
+                .find('"')
+                .ok_or_else(|| DirectiveErr::UnclosedQuote(rest.to_owned()))?;
+            words.push(Word {
+                text: quoted[..end].to_owned(),
+                quoted: true,
+            });
+            rest = &quoted[end + 1..];
+        } else {
+            let end = rest.find(char::is_whitespace).unwrap_or(rest.len());
+            words.push(Word {
+                text: rest[..end].to_owned(),
+                quoted: false,
+            });
+            rest = &rest[end..];
+        }
+        rest = rest.trim_start();
+    }
+    Ok(words)
+}
+
+impl Directive {
+    pub const INCLUDE: &'static str = "include";
+    pub const MACRO: &'static str = "macro";
+    pub const END: &'static str = "end";
+    pub const USE: &'static str = "use";
+    pub const FILL: &'static str = "fill";
+    pub const ENTRY: &'static str = "entry";
+
+    pub const KEYWORDS: [&'static str; 6] = [
+        Self::INCLUDE,
+        Self::MACRO,
+        Self::END,
+        Self::USE,
+        Self::FILL,
+        Self::ENTRY,
+    ];
+
+    /// Whether a line of code is a directive rather than an instruction.
+    /// A keyword followed by an arg, like `end\ttrue`, is an instruction at a state with that label.
+    pub fn is_directive(line: &str) -> bool {
+        let mut words = line.split_whitespace();
+        let first = words.next().unwrap_or("");
+        let second_is_arg = words
+            .next()
+            .is_some_and(|word| word.parse::<ArgPattern>().is_ok());
+        Self::KEYWORDS.contains(&first) && !second_is_arg
+    }
+
+    /// Parses a line of code, which must start with one of the [`Directive::KEYWORDS`].
+    pub fn parse(line: &str) -> Result<Self, DirectiveErr> {
+        use DirectiveErr::*;
+
+        let mut words = split_words(line)?.into_iter();
+        let keyword = words.next().map(|word| word.text);
+        let directive = match keyword.as_deref() {
+            Some(Self::INCLUDE) => match words.next() {
+                Some(Word { text, quoted: true }) => Self::Include(text),
+                _ => return Err(ExpectedPath(Self::INCLUDE)),
+            },
+            Some(Self::MACRO) => Self::Macro {
+                name: words.next().ok_or(ExpectedName(Self::MACRO))?.text,
+                params: words.by_ref().map(|word| word.text).collect(),
+            },
+            Some(Self::END) => Self::End,
+            Some(Self::USE) => Self::Use {
+                name: words.next().ok_or(ExpectedName(Self::USE))?.text,
+                args: words.by_ref().map(|word| word.text).collect(),
+            },
+            Some(Self::FILL) => {
+                let policy = words.next().ok_or(ExpectedFillPolicy(Self::FILL))?.text;
+                Self::Fill(policy.parse().map_err(|_| UnknownFillPolicy(policy))?)
+            }
+            Some(Self::ENTRY) => {
+                let state = words.next().ok_or(ExpectedState(Self::ENTRY))?.text;
+                let state = state
+                    .parse()
+                    .map_err(|e: BadStateRef| BadState(Self::ENTRY, state, e.to_string()))?;
+                let arg = match words.next() {
+                    Some(word) => word
+                        .text
+                        .parse()
+                        .map_err(|_| BadArg(Self::ENTRY, word.text))?,
+                    None => false,
+                };
+                Self::Entry(state, arg)
+            }
+            _ => unreachable!("Tried to parse an instruction as a directive."),
+        };
+
+        if let Some(extra) = words.next() {
+            return Err(TooManyArgs(directive.keyword(), extra.text));
+        }
+        Ok(directive)
+    }
+
+    pub fn keyword(&self) -> &'static str {
+        match self {
+            Self::Include(_) => Self::INCLUDE,
+            Self::Macro { .. } => Self::MACRO,
+            Self::End => Self::END,
+            Self::Use { .. } => Self::USE,
+            Self::Fill(_) => Self::FILL,
+            Self::Entry(..) => Self::ENTRY,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::rie::RieProgram;
+
+    #[test]
+    fn keywords_start_directives() {
+        assert!(Directive::is_directive("\tend"));
+        assert!(Directive::is_directive("\tuse register_demo a b"));
+        assert!(Directive::is_directive("\tmacro register_demo a b"));
+        assert!(Directive::is_directive(
+            "\tinclude \"lib/register_demo.rie\""
+        ));
+        assert!(!Directive::is_directive("\t0\tfalse\t1\ttrue"));
+    }
+
+    #[test]
+    fn entry_and_fill_still_parse() {
+        assert!(Directive::is_directive("\tentry\tmain"));
+        assert!(Directive::is_directive("\tfill\ttrap"));
+        assert_eq!(
+            Directive::parse("entry main true").unwrap(),
+            Directive::Entry(StateRef::Label("main".to_owned()), true)
+        );
+        assert_eq!(
+            Directive::parse("fill trap").unwrap(),
+            Directive::Fill(FillPolicy::Trap)
+        );
+    }
+
+    #[test]
+    fn keywords_followed_by_an_arg_are_labels() {
+        for keyword in Directive::KEYWORDS {
+            for arg in ["true", "false", "*"] {
+                assert!(!Directive::is_directive(&format!(
+                    "\t{keyword}\t{arg}\t0\t\t?"
+                )));
+            }
+            let source = format!(
+                "\tstate\targ\tgoto\tread\treg\n\
+                \t0\tfalse\t{keyword}\ttrue\n\
+                \t{keyword}\ttrue\t0\t\t?\n"
+            );
+            RieProgram::parse(&source).unwrap();
+        }
+    }
+}
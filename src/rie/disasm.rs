@@ -0,0 +1,232 @@
+use super::{
+    assembler::FillPolicy, header::RegisterNames, register_cmd::RegisterCmd, tm_cmd::TMCmd,
+    RieProgram,
+};
+use crate::helpers::largest_bit;
+use std::collections::{BTreeMap, BTreeSet};
+use thiserror::Error;
+
+#[derive(Error, Debug)]
+pub enum DisasmErr {
+    #[error("The RLE has no pattern data.")]
+    Empty,
+
+    #[error("Unexpected character {0:?} in the RLE.")]
+    BadChar(char),
+
+    #[error(
+        "Found {0} ROM columns, but a ROM must have a power of two columns.\n\
+        Is this an RLE made by rieasm?"
+    )]
+    BadColumnCount(usize),
+
+    #[error(
+        "Row {0} of the ROM does not line up with the first row.\n\
+        Is this an RLE made by rieasm?"
+    )]
+    BadRow(usize),
+
+    #[error(
+        "The ROM has {0} rows, which doesn't fit {1} state bits and a 2-bit read.\n\
+        Is this an RLE made by rieasm?"
+    )]
+    BadRowCount(usize, u32),
+
+    #[error("Column {0} has an invalid bit pattern {1:?} for Register {2}.")]
+    BadRegisterBits(usize, [bool; 4], usize),
+
+    #[error("Column {0} reads both true and false at once.")]
+    BadReadBits(usize),
+}
+
+impl RegisterCmd {
+    pub fn disassemble(bits: [bool; 4]) -> Option<Self> {
+        use RegisterCmd::*;
+        [Noop, Push, Pull, Flip, FlipPull, Read]
+            .into_iter()
+            .find(|cmd| cmd.assemble() == bits)
+    }
+}
+
+/// Reads the cells of an RLE into a map from (y, x) to cell state.
+fn parse_rle(rle: &str) -> Result<BTreeMap<(usize, usize), char>, DisasmErr> {
+    use DisasmErr::*;
+
+    let mut cells = BTreeMap::new();
+    let (mut x, mut y) = (0, 0);
+    let mut count = String::new();
+    let body = rle
+        .lines()
+        .filter(|line| !line.starts_with('#') && !line.trim_start().starts_with("x "))
+        .flat_map(|line| line.trim().chars());
+    for c in body {
+        if c.is_ascii_digit() {
+            count.push(c);
+            continue;
+        }
+        let n = count.parse().unwrap_or(1);
+        count.clear();
+        match c {
+            '!' => break,
+            '$' => {
+                y += n;
+                x = 0;
+            }
+            '.' | 'b' => x += n,
+            'A'..='X' | 'o' => {
+                for _ in 0..n {
+                    cells.insert((y, x), c);
+                    x += 1;
+                }
+            }
+            c => return Err(BadChar(c)),
+        }
+    }
+    Ok(cells)
+}
+
+impl RieProgram {
+    /// Reverses [`RieProgram::rle`].
+    pub fn disassemble(rle: &str) -> Result<Self, DisasmErr> {
+        use DisasmErr::*;
+
+        let cells = parse_rle(rle)?;
+
+        // group cells into rows, ordered top to bottom
+        let mut rows: BTreeMap<usize, Vec<(usize, bool)>> = BTreeMap::new();
+        for (&(y, x), &c) in cells.iter() {
+            rows.entry(y).or_default().push((x, c == 'B'));
+        }
+        let rows = rows.into_iter().collect::<Vec<_>>();
+        let (_y, first_row) = rows.first().ok_or(Empty)?;
+
+        let column_count = first_row.len();
+        if column_count < 2 || !column_count.is_power_of_two() {
+            return Err(BadColumnCount(column_count));
+        }
+        let state_bits = largest_bit(column_count / 2 - 1);
+
+        // every row is the first row shifted right by its index
+        let origin = first_row[0].0;
+        let columns = first_row
+            .iter()
+            .map(|&(x, _)| x - origin)
+            .collect::<Vec<_>>();
+        let mut bits = vec![];
+        for (i, (_y, row)) in rows.iter().enumerate() {
+            let offsets = row.iter().map(|&(x, _)| x.checked_sub(origin + i));
+            if !offsets.eq(columns.iter().copied().map(Some)) {
+                return Err(BadRow(i));
+            }
+            bits.push(row.iter().map(|&(_x, bit)| bit).collect::<Vec<_>>());
+        }
+
+        // rows are laid out as reversed registers, then read, then goto
+        let register_rows = bits
+            .len()
+            .checked_sub(2 + state_bits as usize)
+            .filter(|rows| rows % 4 == 0)
+            .ok_or(BadRowCount(bits.len(), state_bits))?;
+        let register_count = register_rows / 4;
+
+        // segments must be spaced further apart than rows
+        let gaps = rows.windows(2).map(|pair| pair[1].0 - pair[0].0);
+        let boundaries = gaps
+            .enumerate()
+            .filter(|&(_i, gap)| gap > 4)
+            .map(|(i, _gap)| i + 1)
+            .collect::<BTreeSet<_>>();
+        let expected = (1..=register_count)
+            .map(|i| i * 4)
+            .chain((state_bits > 0).then_some(register_rows + 2))
+            .collect::<BTreeSet<_>>();
+        if boundaries != expected {
+            return Err(BadRowCount(bits.len(), state_bits));
+        }
+
+        let mut commands = vec![];
+        for state in 0..column_count / 2 {
+            let mut pair = [TMCmd::default(), TMCmd::default()];
+            for (arg, cmd) in pair.iter_mut().enumerate() {
+                let column = state * 2 + arg;
+                let bit = |row: usize| bits[row][column];
+
+                for register in 0..register_count {
+                    let row = (register_count - 1 - register) * 4;
+                    let nibble = [bit(row), bit(row + 1), bit(row + 2), bit(row + 3)];
+                    cmd.register_cmds.push(
+                        RegisterCmd::disassemble(nibble)
+                            .ok_or(BadRegisterBits(column, nibble, register))?,
+                    );
+                }
+                while cmd.register_cmds.last() == Some(&RegisterCmd::Noop) {
+                    cmd.register_cmds.pop();
+                }
+
+                cmd.read = match [bit(register_rows), bit(register_rows + 1)] {
+                    [false, false] => None,
+                    [true, false] => Some(false),
+                    [false, true] => Some(true),
+                    [true, true] => return Err(BadReadBits(column)),
+                };
+
+                cmd.goto = (0..state_bits as usize)
+                    .map(|i| (bit(register_rows + 2 + i) as u32) << i)
+                    .sum();
+            }
+            commands.push(pair);
+        }
+
+        Ok(RieProgram {
+            sources: vec![[None, None]; commands.len()],
+            commands,
+            state_bits,
+            register_names: RegisterNames::unnamed(register_count),
+            virtual_registers: vec![],
+            split_states: 0,
+            // there's no telling which entries were filled in
+            fill: FillPolicy::Halt,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Checks that disassembling a program's RLE gives back the same ROM.
+    fn round_trips(source: &str) {
+        let (program, _warnings) = RieProgram::parse(source).unwrap();
+        let disassembled = RieProgram::disassemble(&program.rle()).unwrap();
+        assert_eq!(disassembled.state_bits(), program.state_bits());
+        assert_eq!(disassembled.register_count(), program.register_count());
+        assert_eq!(disassembled.assemble(), program.assemble());
+        assert_eq!(disassembled.rle(), program.rle());
+    }
+
+    #[test]
+    fn round_trips_the_example() {
+        round_trips(include_str!("../../program.rie"));
+    }
+
+    #[test]
+    fn round_trips_flip_if_else() {
+        round_trips(include_str!("../../golly/FlipIfElse.rie"));
+    }
+
+    #[test]
+    fn rejects_bad_rles() {
+        assert!(matches!(
+            RieProgram::disassemble("!"),
+            Err(DisasmErr::Empty)
+        ));
+        assert!(matches!(
+            RieProgram::disassemble("3B!"),
+            Err(DisasmErr::BadColumnCount(3))
+        ));
+        assert!(matches!(
+            RieProgram::disassemble("B$Z!"),
+            Err(DisasmErr::BadChar('Z'))
+        ));
+    }
+}
@@ -0,0 +1,152 @@
+use super::{
+    control_flow::{is_routine_start, nesting},
+    directive::Directive,
+    header::HeaderFormat,
+    register_cmd::RegisterCmd,
+    tm_cmd::TMCmd,
+    RieProgram,
+};
+use crate::helpers::get_tokens;
+
+/// Joins the cells of a line with tabs, right-aligning numbered states and dropping empty trailing cells.
+fn join_cells(mut cells: Vec<String>, state_digits: usize) -> String {
+    while cells.last().is_some_and(|cell| cell.is_empty()) {
+        cells.pop();
+    }
+    if let Some(state) = cells.first_mut().filter(|state| is_number(state)) {
+        *state = format!("{state:>state_digits$}");
+    }
+    format!("\t{}", cells.join("\t"))
+}
+
+fn is_number(cell: &str) -> bool {
+    !cell.is_empty() && cell.chars().all(|c| c.is_ascii_digit())
+}
+
+/// Normalizes the whitespace of a `.rie` source without changing its meaning.
+/// Comments are kept as they are, save for trailing whitespace.
+pub fn format_source(source: &str) -> String {
+    let is_code = |line: &&str| line.starts_with('\t') && !Directive::is_directive(line);
+    let state_digits = source
+        .lines()
+        .filter(|line| line.starts_with(|c: char| c.is_whitespace()))
+        .skip(1)
+        .filter(is_code)
+        .filter_map(|line| get_tokens(line).next())
+        .map(str::trim)
+        .filter(|state| is_number(state))
+        .map(str::len)
+        .max()
+        .unwrap_or(0);
+
+    let mut seen_header = false;
+    // how many blocks of a routine the line is inside of
+    let mut depth = 0;
+    let mut out = String::new();
+    for line in source.lines() {
+        if line.trim().is_empty() {
+            out.push('\n');
+            continue;
+        }
+        let is_header = !seen_header && line.starts_with(|c: char| c.is_whitespace());
+        let is_structured = seen_header && (depth > 0 || is_routine_start(line));
+        if is_structured && line.starts_with(|c: char| c.is_whitespace()) {
+            let (closes, opens) = nesting(line);
+            if closes {
+                depth = usize::saturating_sub(depth, 1);
+            }
+            out.push_str(&"\t".repeat(depth + 1));
+            out.push_str(line.trim());
+            if opens {
+                depth += 1;
+            }
+        } else if seen_header && line.starts_with('\t') && Directive::is_directive(line) {
+            out.push_str(&format!("\t{}", line.trim()));
+        } else if is_header || is_code(&line) {
+            let cells = get_tokens(line)
+                .map(|cell| cell.trim().to_owned())
+                .collect();
+            out.push_str(&join_cells(cells, if is_header { 0 } else { state_digits }));
+            seen_header = true;
+        } else {
+            out.push_str(line.trim_end());
+        }
+        out.push('\n');
+    }
+    out
+}
+
+impl RegisterCmd {
+    pub fn token(&self) -> &'static str {
+        Self::VALID_COMMANDS
+            .iter()
+            .find_map(|&(token, cmd)| (cmd == *self).then_some(token))
+            .unwrap_or("")
+    }
+}
+
+impl RieProgram {
+    /// Writes the program back out as `.rie` source.
+    /// Commands that halt without doing anything are left out, as they are what unspecified lines become.
+    pub fn to_rie(&self) -> String {
+        let state_digits = (self.commands.len() - 1).to_string().len();
+
+        let mut out = String::from("-- generated by rieasm\n");
+        let header = HeaderFormat::physical(self.register_names.clone()).cells();
+        out.push_str(&join_cells(header, 0));
+        out.push('\n');
+
+        for (state, pair) in self.commands.iter().enumerate() {
+            for (arg, cmd) in pair.iter().enumerate() {
+                if cmd.is_halt() {
+                    continue;
+                }
+                let TMCmd {
+                    goto,
+                    read,
+                    register_cmds,
+                } = cmd;
+                let mut cells = vec![
+                    state.to_string(),
+                    (arg == 1).to_string(),
+                    if *goto as usize == state {
+                        String::new()
+                    } else {
+                        goto.to_string()
+                    },
+                    read.map_or_else(String::new, |read| read.to_string()),
+                ];
+                cells.extend(register_cmds.iter().map(|cmd| cmd.token().to_owned()));
+                out.push_str(&join_cells(cells, state_digits));
+                out.push('\n');
+            }
+        }
+        out
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn formats_code_that_doesnt_assemble() {
+        // `nowhere` isn't a label, and there's no register 3
+        let source = "\
+\tstate\targ  \tgoto\tread\treg
+\t0\tfalse\tnowhere\ttrue\t>\t\t
+\t10\ttrue\t0\t\t?\t\t\t%
+";
+        let formatted = format_source(source);
+        assert!(RieProgram::parse(source).is_err());
+        assert_eq!(
+            formatted,
+            "\
+\tstate\targ\tgoto\tread\treg
+\t 0\tfalse\tnowhere\ttrue\t>
+\t10\ttrue\t0\t\t?\t\t\t%
+"
+        );
+        assert_eq!(format_source(&formatted), formatted);
+    }
+}
@@ -1,4 +1,4 @@
-use std::str::FromStr;
+use std::{fmt::Display, str::FromStr};
 use thiserror::Error;
 
 use crate::helpers::get_tokens;
@@ -23,20 +23,138 @@ pub enum HeaderErr {
 
     #[error(
         "This version of rieasm does not support the {0} header.\n\
-        The only valid headers are {}, and {}.",
+        The only valid headers are {}, {}, and {}.",
         HeaderFormat::PRIMARY_HEADERS.join(", "),
         HeaderFormat::REGISTER_HEADER,
+        HeaderFormat::VIRTUAL_HEADER,
     )]
     UnsupportedHeader(String),
+
+    #[error(
+        "{0:?} can't be the name of a register.\n\
+        Register names must start with a letter or `_`, followed by letters, digits or `_`."
+    )]
+    BadRegisterName(String),
+
+    #[error("There is more than one register named {0:?}.")]
+    DuplicateRegisterName(String),
+}
+
+/// The names given to registers in the header, if any.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct RegisterNames(pub Vec<Option<String>>);
+
+impl RegisterNames {
+    pub fn unnamed(register_count: usize) -> Self {
+        Self(vec![None; register_count])
+    }
+
+    pub fn get(&self, register: usize) -> Option<&str> {
+        self.0.get(register)?.as_deref()
+    }
+
+    /// How a register is referred to in messages: by its name if it has one, or its number if it doesn't.
+    pub fn describe(&self, register: usize) -> String {
+        self.get(register)
+            .map_or_else(|| format!("Register {register}"), str::to_owned)
+    }
+
+    /// Finds a register by its name or number.
+    pub fn find(&self, target: &str) -> Option<usize> {
+        match target.parse::<usize>() {
+            Ok(register) => (register < self.0.len()).then_some(register),
+            Err(_) => self
+                .0
+                .iter()
+                .position(|name| name.as_deref() == Some(target)),
+        }
+    }
+
+    pub(crate) fn is_name(name: &str) -> bool {
+        let mut chars = name.chars();
+        chars
+            .next()
+            .is_some_and(|c| c.is_ascii_alphabetic() || c == '_')
+            && chars.all(|c| c.is_ascii_alphanumeric() || c == '_')
+    }
 }
 
 pub struct HeaderFormat {
-    pub register_count: usize,
+    /// The names of every register column, physical or virtual.
+    pub register_names: RegisterNames,
+    /// Which register columns are virtual registers,
+    /// which the assembler gets to put wherever they fit.
+    pub is_virtual: Vec<bool>,
 }
 
 impl HeaderFormat {
     pub const PRIMARY_HEADERS: [&'static str; 4] = ["state", "arg", "goto", "read"];
     pub const REGISTER_HEADER: &'static str = "reg";
+    pub const VIRTUAL_HEADER: &'static str = "vreg";
+    /// Separates a register header from the register's name, as in `reg:prog`.
+    pub const NAME_SEPARATOR: char = ':';
+
+    /// A header with only physical registers.
+    pub fn physical(register_names: RegisterNames) -> Self {
+        Self {
+            is_virtual: vec![false; register_names.0.len()],
+            register_names,
+        }
+    }
+
+    /// How many register columns lines of code can have.
+    pub fn column_count(&self) -> usize {
+        self.register_names.0.len()
+    }
+
+    /// How many registers the target machine has.
+    pub fn register_count(&self) -> usize {
+        self.is_virtual
+            .iter()
+            .filter(|&&is_virtual| !is_virtual)
+            .count()
+    }
+
+    /// Whether code written for `other` means the same thing under this header.
+    /// Registers only have to have the same name if both headers name them.
+    pub fn is_compatible(&self, other: &HeaderFormat) -> bool {
+        self.is_virtual == other.is_virtual
+            && self
+                .register_names
+                .0
+                .iter()
+                .zip(other.register_names.0.iter())
+                .all(|pair| match pair {
+                    (Some(a), Some(b)) => a == b,
+                    _ => true,
+                })
+    }
+
+    /// The cells of the header line.
+    pub fn cells(&self) -> Vec<String> {
+        let registers = self.register_names.0.iter().zip(&self.is_virtual);
+        Self::PRIMARY_HEADERS
+            .iter()
+            .map(|&header| header.to_owned())
+            .chain(registers.map(|(name, &is_virtual)| {
+                let header = if is_virtual {
+                    Self::VIRTUAL_HEADER
+                } else {
+                    Self::REGISTER_HEADER
+                };
+                match name {
+                    Some(name) => format!("{header}{}{name}", Self::NAME_SEPARATOR),
+                    None => header.to_owned(),
+                }
+            }))
+            .collect()
+    }
+}
+
+impl Display for HeaderFormat {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.cells().join(" "))
+    }
 }
 
 impl FromStr for HeaderFormat {
@@ -58,15 +176,33 @@ impl FromStr for HeaderFormat {
             }
         }
 
-        // Count register headers
-        let mut register_count = 0;
+        // Count register headers, which may be named
+        let mut names: Vec<Option<String>> = vec![];
+        let mut is_virtual = vec![];
         for token in tokens {
-            if token != Self::REGISTER_HEADER {
-                return Err(UnsupportedHeader(token.to_owned()));
+            let (header, name) = match token.split_once(Self::NAME_SEPARATOR) {
+                Some((header, name)) => (header, Some(name)),
+                None => (token, None),
+            };
+            match header {
+                Self::REGISTER_HEADER => is_virtual.push(false),
+                Self::VIRTUAL_HEADER => is_virtual.push(true),
+                _ => return Err(UnsupportedHeader(token.to_owned())),
+            }
+            if let Some(name) = name {
+                if !RegisterNames::is_name(name) {
+                    return Err(BadRegisterName(name.to_owned()));
+                }
+                if names.iter().any(|other| other.as_deref() == Some(name)) {
+                    return Err(DuplicateRegisterName(name.to_owned()));
+                }
             }
-            register_count += 1;
+            names.push(name.map(str::to_owned));
         }
 
-        Ok(HeaderFormat { register_count })
+        Ok(HeaderFormat {
+            register_names: RegisterNames(names),
+            is_virtual,
+        })
     }
 }
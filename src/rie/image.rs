@@ -0,0 +1,92 @@
+use super::RieProgram;
+use thiserror::Error;
+
+#[derive(Error, Debug)]
+pub enum ImageErr {
+    #[error("The program has {0} state bits, but a ROM image only has room for 255.")]
+    TooManyStateBits(u32),
+
+    #[error("The program has {0} registers, but a ROM image only has room for 255.")]
+    TooManyRegisters(usize),
+
+    #[error("The program's words are {0} bits long, but a ROM image only has room for 65535.")]
+    WordTooLong(usize),
+}
+
+impl RieProgram {
+    /// The first bytes of every ROM image.
+    pub const IMAGE_MAGIC: [u8; 4] = *b"RIE1";
+
+    /// How many bits every word of the ROM image has:
+    /// 4 for each register, 2 for the read, and the goto.
+    pub fn word_bits(&self) -> usize {
+        4 * self.register_count() + 2 + self.state_bits as usize
+    }
+
+    /// The assembled ROM as a packed image, for other simulators and test benches.
+    ///
+    /// The image starts with an 8 byte header:
+    /// [`RieProgram::IMAGE_MAGIC`], the number of state bits, the number of registers,
+    /// and the number of bits in a word as a little endian `u16`.
+    /// Then comes one word for every demux address (`state << 1 | arg`) in order,
+    /// each taking as few whole bytes as it fits in, little endian.
+    /// Bit 0 of a word is the top cell of its column in the RLE, and so on down the column.
+    /// Fails if the program is too big for the header.
+    pub fn rom_image(&self) -> Result<Vec<u8>, ImageErr> {
+        use ImageErr::*;
+
+        let word_bits = self.word_bits();
+        let word_bytes = word_bits.div_ceil(8);
+
+        let mut image = Self::IMAGE_MAGIC.to_vec();
+        image.push(u8::try_from(self.state_bits).map_err(|_| TooManyStateBits(self.state_bits))?);
+        let register_count = self.register_count();
+        image.push(u8::try_from(register_count).map_err(|_| TooManyRegisters(register_count))?);
+        let header_word_bits = u16::try_from(word_bits).map_err(|_| WordTooLong(word_bits))?;
+        image.extend(header_word_bits.to_le_bytes());
+
+        for column in self.assemble().iter().flatten() {
+            let mut word = vec![0u8; word_bytes];
+            for (i, _bit) in column.iter().flatten().enumerate().filter(|(_i, &bit)| bit) {
+                word[i / 8] |= 1 << (i % 8);
+            }
+            image.extend(word);
+        }
+        Ok(image)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::rie::header::RegisterNames;
+
+    const SOURCE: &str = "\
+\tstate\targ\tgoto\tread\treg\treg
+\t0\tfalse\t1\ttrue\t>\t%
+\t1\ttrue\t0\t\t?
+";
+
+    #[test]
+    fn writes_the_header() {
+        let (program, _warnings) = RieProgram::parse(SOURCE).unwrap();
+        let image = program.rom_image().unwrap();
+        let word_bits = program.word_bits();
+        assert_eq!(image[..4], RieProgram::IMAGE_MAGIC);
+        assert_eq!(image[4], program.state_bits() as u8);
+        assert_eq!(image[5], 2);
+        assert_eq!(image[6..8], (word_bits as u16).to_le_bytes());
+        let addresses = 2 << program.state_bits();
+        assert_eq!(image.len(), 8 + addresses * word_bits.div_ceil(8));
+    }
+
+    #[test]
+    fn rejects_too_many_registers() {
+        let (mut program, _warnings) = RieProgram::parse(SOURCE).unwrap();
+        program.register_names = RegisterNames::unnamed(256);
+        assert!(matches!(
+            program.rom_image(),
+            Err(ImageErr::TooManyRegisters(256))
+        ));
+    }
+}
@@ -0,0 +1,307 @@
+use super::{
+    assembler::FillPolicy, header::RegisterNames, register_cmd::RegisterCmd, source::SourcePos,
+    tm_cmd::TMCmd, RieProgram,
+};
+use serde::{Deserialize, Serialize};
+use std::{path::PathBuf, sync::Arc};
+use thiserror::Error;
+
+/// The version of the JSON format written by [`RieProgram::to_json`].
+pub const VERSION: u32 = 1;
+
+/// The most state bits a JSON program may have, to keep typos from asking for huge programs.
+pub const MAX_STATE_BITS: u32 = 20;
+
+/// The most registers a JSON program may have, for the same reason.
+pub const MAX_REGISTERS: usize = 255;
+
+#[derive(Error, Debug)]
+pub enum JsonErr {
+    #[error("{0}")]
+    Parse(#[from] serde_json::Error),
+
+    #[error("This is version {0} of the JSON format, but only version {VERSION} is supported.")]
+    BadVersion(u32),
+
+    #[error("The program has {0} state bits, but at most {MAX_STATE_BITS} are allowed.")]
+    TooManyStateBits(u32),
+
+    #[error("The program has {0} registers, but at most {MAX_REGISTERS} are allowed.")]
+    TooManyRegisters(usize),
+
+    #[error("There are {0} register names, but register_count is {1}.")]
+    BadNameCount(usize, usize),
+
+    #[error("There are {0} lists of virtual registers, but register_count is {1}.")]
+    BadVirtualRegisterCount(usize, usize),
+
+    #[error(
+        "{0:?} can't be the name of a register.\n\
+        Register names must start with a letter or `_`, followed by letters, digits or `_`."
+    )]
+    BadRegisterName(String),
+
+    #[error("There is more than one register named {0:?}.")]
+    DuplicateRegisterName(String),
+
+    #[error("State {0} doesn't fit in {1} state bits.")]
+    StateOutOfRange(u32, u32),
+
+    #[error("State {0} with Arg {1} goes to State {2}, which doesn't fit in {3} state bits.")]
+    GotoOutOfRange(u32, bool, u32, u32),
+
+    #[error(
+        "State {0} with Arg {1} has commands for {2} registers, but the program only has {3}."
+    )]
+    TooManyRegisterCmds(u32, bool, usize, usize),
+
+    #[error(
+        "State {0} with Arg {1} reads more than once.\n\
+        There must only be at most 1 read per command."
+    )]
+    MultiRead(u32, bool),
+
+    #[error("State {0} with Arg {1} is in the program more than once.")]
+    Duplicate(u32, bool),
+}
+
+/// A [`SourcePos`] in JSON.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct SourceJson {
+    file: PathBuf,
+    line: usize,
+    /// Where the macro containing this line was used, if it came from a macro.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    expanded_from: Option<Box<SourceJson>>,
+}
+
+impl From<&SourcePos> for SourceJson {
+    fn from(pos: &SourcePos) -> Self {
+        Self {
+            file: pos.file.to_path_buf(),
+            line: pos.line,
+            expanded_from: pos.expanded_from.as_deref().map(|pos| Box::new(pos.into())),
+        }
+    }
+}
+
+impl From<SourceJson> for SourcePos {
+    fn from(json: SourceJson) -> Self {
+        Self {
+            file: Arc::from(json.file),
+            line: json.line,
+            expanded_from: json.expanded_from.map(|pos| Arc::new((*pos).into())),
+        }
+    }
+}
+
+/// One entry of the ROM in JSON: a [`TMCmd`], along with where it goes and where it came from.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct EntryJson {
+    state: u32,
+    arg: bool,
+    #[serde(flatten)]
+    cmd: TMCmd,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    source: Option<SourceJson>,
+}
+
+/// A [`RieProgram`] in JSON.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct ProgramJson {
+    version: u32,
+    state_bits: u32,
+    register_count: usize,
+    /// The name of every register, or `null` for registers without one.
+    /// May be left out if no register has a name.
+    #[serde(default)]
+    register_names: Vec<Option<String>>,
+    /// The virtual registers put in every register.
+    /// May be left out if the program has no virtual registers.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    virtual_registers: Vec<Vec<String>>,
+    #[serde(default)]
+    split_states: usize,
+    #[serde(default)]
+    fill: FillPolicy,
+    /// Every entry that doesn't just halt. Entries that are left out halt.
+    commands: Vec<EntryJson>,
+}
+
+impl RieProgram {
+    /// Writes the program as JSON, in the format described in the README.
+    pub fn to_json(&self) -> String {
+        let commands = self
+            .commands
+            .iter()
+            .enumerate()
+            .flat_map(|(state, pair)| {
+                pair.iter().enumerate().filter_map(move |(arg, cmd)| {
+                    let source = self.source(state, arg == 1);
+                    (!cmd.is_halt() || source.is_some()).then(|| EntryJson {
+                        state: state as u32,
+                        arg: arg == 1,
+                        cmd: cmd.clone(),
+                        source: source.map(SourceJson::from),
+                    })
+                })
+            })
+            .collect();
+        let json = ProgramJson {
+            version: VERSION,
+            state_bits: self.state_bits,
+            register_count: self.register_count(),
+            register_names: self.register_names.0.clone(),
+            virtual_registers: match self.virtual_registers.iter().all(Vec::is_empty) {
+                true => vec![],
+                false => self.virtual_registers.clone(),
+            },
+            split_states: self.split_states,
+            fill: self.fill,
+            commands,
+        };
+        serde_json::to_string_pretty(&json).expect("A program can always be written as JSON.")
+    }
+
+    /// Reverses [`RieProgram::to_json`], checking that the program fits in the machine.
+    pub fn from_json(json: &str) -> Result<Self, JsonErr> {
+        use JsonErr::*;
+
+        let json: ProgramJson = serde_json::from_str(json)?;
+        if json.version != VERSION {
+            return Err(BadVersion(json.version));
+        }
+        let state_bits = json.state_bits;
+        if state_bits > MAX_STATE_BITS {
+            return Err(TooManyStateBits(state_bits));
+        }
+        let register_count = json.register_count;
+        if register_count > MAX_REGISTERS {
+            return Err(TooManyRegisters(register_count));
+        }
+        let names = json.register_names.iter().flatten().collect::<Vec<_>>();
+        for (i, name) in names.iter().enumerate() {
+            if !RegisterNames::is_name(name) {
+                return Err(BadRegisterName(name.to_string()));
+            }
+            if names[..i].contains(name) {
+                return Err(DuplicateRegisterName(name.to_string()));
+            }
+        }
+        let register_names = match json.register_names.len() {
+            0 => RegisterNames::unnamed(register_count),
+            n if n == register_count => RegisterNames(json.register_names),
+            n => return Err(BadNameCount(n, register_count)),
+        };
+        let virtual_registers = match json.virtual_registers.len() {
+            0 => vec![vec![]; register_count],
+            n if n == register_count => json.virtual_registers,
+            n => return Err(BadVirtualRegisterCount(n, register_count)),
+        };
+
+        let state_count = 1 << state_bits;
+        let mut commands = vec![[TMCmd::default(), TMCmd::default()]; state_count];
+        let mut sources = vec![[None, None]; state_count];
+        let mut defined = vec![[false; 2]; state_count];
+        for EntryJson {
+            state,
+            arg,
+            mut cmd,
+            source,
+        } in json.commands
+        {
+            if state as usize >= state_count {
+                return Err(StateOutOfRange(state, state_bits));
+            }
+            if cmd.goto as usize >= state_count {
+                return Err(GotoOutOfRange(state, arg, cmd.goto, state_bits));
+            }
+            if cmd.register_cmds.len() > register_count {
+                let count = cmd.register_cmds.len();
+                return Err(TooManyRegisterCmds(state, arg, count, register_count));
+            }
+            let reads = cmd
+                .register_cmds
+                .iter()
+                .filter(|&&cmd| cmd == RegisterCmd::Read)
+                .count()
+                + cmd.read.is_some() as usize;
+            if reads > 1 {
+                return Err(MultiRead(state, arg));
+            }
+            let entry = &mut defined[state as usize][arg as usize];
+            if *entry {
+                return Err(Duplicate(state, arg));
+            }
+            *entry = true;
+
+            cmd.register_cmds.resize(register_count, RegisterCmd::Noop);
+            commands[state as usize][arg as usize] = cmd;
+            sources[state as usize][arg as usize] = source.map(SourcePos::from);
+        }
+
+        Ok(Self {
+            commands,
+            state_bits,
+            register_names,
+            virtual_registers,
+            split_states: json.split_states,
+            sources,
+            fill: json.fill,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SOURCE: &str = "\
+\tstate\targ\tgoto\tread\treg:prog\treg:mem
+\t0\tfalse\t1\ttrue\t>\t%
+\t1\ttrue\t0\t\t?
+";
+
+    /// The JSON of a small program, with some of its fields changed.
+    fn changed(change: impl FnOnce(&mut serde_json::Value)) -> String {
+        let (program, _warnings) = RieProgram::parse(SOURCE).unwrap();
+        let mut json = serde_json::from_str(&program.to_json()).unwrap();
+        change(&mut json);
+        json.to_string()
+    }
+
+    #[test]
+    fn round_trips() {
+        let (program, _warnings) = RieProgram::parse(SOURCE).unwrap();
+        let loaded = RieProgram::from_json(&program.to_json()).unwrap();
+        assert_eq!(loaded.rle(), program.rle());
+        assert_eq!(loaded.register_names(), program.register_names());
+    }
+
+    #[test]
+    fn rejects_too_many_registers() {
+        let json = changed(|json| {
+            json["register_count"] = (MAX_REGISTERS + 1).into();
+            json["register_names"] = serde_json::Value::Array(vec![]);
+        });
+        assert!(matches!(
+            RieProgram::from_json(&json),
+            Err(JsonErr::TooManyRegisters(_))
+        ));
+    }
+
+    #[test]
+    fn rejects_bad_register_names() {
+        let json = changed(|json| json["register_names"][1] = "1mem".into());
+        assert!(matches!(
+            RieProgram::from_json(&json),
+            Err(JsonErr::BadRegisterName(_))
+        ));
+
+        let json = changed(|json| json["register_names"][1] = "prog".into());
+        assert!(matches!(
+            RieProgram::from_json(&json),
+            Err(JsonErr::DuplicateRegisterName(_))
+        ));
+    }
+}
@@ -0,0 +1,161 @@
+use std::{fmt::Display, str::FromStr};
+use thiserror::Error;
+
+#[derive(Error, Debug)]
+#[error(
+    "{0:?} is neither a state number nor a label.\n\
+    Labels must start with a letter, `_` or `.`, followed by letters, digits, `_` or `.`.\n\
+    Either can be followed by an offset, like `loop+3` or `5-1`."
+)]
+pub struct BadStateRef(String);
+
+/// A state, either by number or by label.
+/// Labels are given the lowest state numbers that aren't already used by the program.
+#[derive(Debug, Clone, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub enum StateRef {
+    Number(u32),
+    Label(String),
+    /// The state some number of states after another one, like `loop+3`.
+    /// Only gotos can be written like this.
+    Offset(Box<StateRef>, i64),
+}
+
+impl StateRef {
+    /// Labels starting with this are local to the macro they're written in.
+    pub const LOCAL_PREFIX: char = '.';
+
+    /// A goto of this goes to the state after the line's own state.
+    pub const NEXT: &'static str = "next";
+
+    /// The state `offset` states after this one.
+    pub fn offset(self, offset: i64) -> Self {
+        match self {
+            Self::Offset(base, before) => Self::Offset(base, before + offset),
+            state if offset == 0 => state,
+            state => Self::Offset(Box::new(state), offset),
+        }
+    }
+
+    /// Parses the 'goto' of a line at state `from`,
+    /// which can also be relative to it, as in `+1`, `-2` or `next`.
+    pub fn parse_goto(s: &str, from: &StateRef) -> Result<Self, BadStateRef> {
+        if s == Self::NEXT {
+            return Ok(from.clone().offset(1));
+        }
+        if s.starts_with(['+', '-']) {
+            let offset = s.parse().map_err(|_| BadStateRef(s.to_owned()))?;
+            return Ok(from.clone().offset(offset));
+        }
+        s.parse()
+    }
+
+    /// The number of this state, if it doesn't depend on any labels.
+    pub fn fixed_number(&self) -> Option<u32> {
+        match self {
+            Self::Number(number) => Some(*number),
+            Self::Label(_) => None,
+            Self::Offset(base, offset) => {
+                let number = base.fixed_number()? as i64 + offset;
+                number.try_into().ok()
+            }
+        }
+    }
+
+    pub fn is_label_start(c: char) -> bool {
+        c.is_ascii_alphabetic() || c == '_' || c == Self::LOCAL_PREFIX
+    }
+
+    pub fn is_label_char(c: char) -> bool {
+        // `#` only shows up in labels generated by the assembler
+        c.is_ascii_alphanumeric() || c == '_' || c == Self::LOCAL_PREFIX || c == '#'
+    }
+}
+
+impl FromStr for StateRef {
+    type Err = BadStateRef;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        // `u32` would take `+1` as state 1, but relative targets are only for gotos, in `parse_goto`
+        if s.starts_with(['+', '-']) {
+            return Err(BadStateRef(s.to_owned()));
+        }
+        if let Ok(number) = s.parse() {
+            return Ok(Self::Number(number));
+        }
+        if let Some(split) = s.rfind(['+', '-']).filter(|&split| split > 0) {
+            let (base, offset) = s.split_at(split);
+            let base = base.parse::<Self>()?;
+            let offset = offset.parse().map_err(|_| BadStateRef(s.to_owned()))?;
+            if let Self::Offset(..) = base {
+                return Err(BadStateRef(s.to_owned()));
+            }
+            return Ok(base.offset(offset));
+        }
+        let mut chars = s.chars();
+        let is_label =
+            chars.next().is_some_and(Self::is_label_start) && chars.all(Self::is_label_char);
+        is_label
+            .then(|| Self::Label(s.to_owned()))
+            .ok_or_else(|| BadStateRef(s.to_owned()))
+    }
+}
+
+impl Display for StateRef {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Number(number) => write!(f, "{number}"),
+            Self::Label(label) => write!(f, "{label}"),
+            Self::Offset(base, offset) => write!(f, "{base}{offset:+}"),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_states() {
+        assert_eq!("5".parse::<StateRef>().unwrap(), StateRef::Number(5));
+        assert_eq!(
+            "loop".parse::<StateRef>().unwrap(),
+            StateRef::Label("loop".to_owned())
+        );
+        assert_eq!(
+            "loop+3".parse::<StateRef>().unwrap(),
+            StateRef::Label("loop".to_owned()).offset(3)
+        );
+        assert_eq!(
+            "5-1".parse::<StateRef>().unwrap(),
+            StateRef::Number(5).offset(-1)
+        );
+    }
+
+    #[test]
+    fn rejects_signs() {
+        assert!("+1".parse::<StateRef>().is_err());
+        assert!("-1".parse::<StateRef>().is_err());
+        assert!("+loop".parse::<StateRef>().is_err());
+    }
+
+    #[test]
+    fn parses_relative_gotos() {
+        let from = StateRef::Label("loop".to_owned());
+        assert_eq!(
+            StateRef::parse_goto("+1", &from).unwrap(),
+            from.clone().offset(1)
+        );
+        assert_eq!(
+            StateRef::parse_goto("-2", &from).unwrap(),
+            from.clone().offset(-2)
+        );
+        assert_eq!(
+            StateRef::parse_goto(StateRef::NEXT, &from).unwrap(),
+            from.clone().offset(1)
+        );
+        assert_eq!(
+            StateRef::parse_goto("7", &from).unwrap(),
+            StateRef::Number(7)
+        );
+    }
+}
@@ -0,0 +1,94 @@
+use super::{rie_line::RieLine, RieProgram};
+
+/// Writes bits the way Golly shows them: `1` for a live cell, `0` for an empty one.
+fn bit_string(bits: &[bool]) -> String {
+    bits.iter()
+        .map(|&bit| if bit { '1' } else { '0' })
+        .collect()
+}
+
+impl RieProgram {
+    /// The title of every segment of a column, in the order [`RieProgram::assemble`] gives them.
+    pub fn segment_titles(&self) -> Vec<String> {
+        let mut titles = (0..self.register_count())
+            .rev()
+            .map(|register| {
+                self.register_names
+                    .get(register)
+                    .map_or_else(|| format!("reg{register}"), str::to_owned)
+            })
+            .collect::<Vec<_>>();
+        titles.push("read".to_owned());
+        titles.push("goto".to_owned());
+        titles
+    }
+
+    /// A listing of every demux address (`state << 1 | arg`) of the ROM,
+    /// with the bits that make up its column, the line it came from, and what it does.
+    pub fn address_listing(&self) -> String {
+        let columns = self.assemble();
+        let titles = self.segment_titles();
+        let widths = titles
+            .iter()
+            .zip(&columns[0][0])
+            .map(|(title, bits)| title.len().max(bits.len()))
+            .collect::<Vec<_>>();
+        let sources = (0..self.len())
+            .map(|address| {
+                self.source(address / 2, address % 2 == 1)
+                    .map_or_else(|| "-".to_owned(), |pos| pos.to_string())
+            })
+            .collect::<Vec<_>>();
+        let address_digits = (self.len() - 1).to_string().len().max("addr".len());
+        let source_width = sources
+            .iter()
+            .map(String::len)
+            .max()
+            .unwrap_or(0)
+            .max("source".len());
+        let state_digits = self.state_digits();
+
+        let mut out = format!(
+            "-- listing generated by rieasm\n\
+            -- {}-bit state ({}-bit demux), {} registers.\n\
+            -- each address is `state << 1 | arg`, and its bits are the cells of its column, from top to bottom.\n\
+            -- goto bits are least significant first.\n",
+            self.state_bits,
+            self.state_bits + 1,
+            self.register_count(),
+        );
+        let segments = |cells: &[String]| {
+            cells
+                .iter()
+                .zip(&widths)
+                .map(|(cell, &width)| format!("{cell:<width$}"))
+                .collect::<Vec<_>>()
+                .join(" ")
+        };
+        out.push_str(&format!(
+            "{:<address_digits$}  {}  {:<source_width$}  command\n",
+            "addr",
+            segments(&titles),
+            "source",
+        ));
+        for (address, source) in sources.iter().enumerate() {
+            let (state, arg) = (address / 2, address % 2 == 1);
+            let bits = columns[state][arg as usize]
+                .iter()
+                .map(|bits| bit_string(bits))
+                .collect::<Vec<_>>();
+            let cmd = RieLine::to_string(
+                state_digits,
+                &self.register_names,
+                state as u32,
+                arg,
+                &self.commands[state][arg as usize],
+            );
+            out.push_str(&format!(
+                "{address:>address_digits$}  {}  {source:<source_width$}  {cmd}\n",
+                segments(&bits),
+            ));
+        }
+        out
+    }
+}
@@ -0,0 +1,225 @@
+use super::{
+    assembler::FillPolicy,
+    directive::{Directive, DirectiveErr},
+    header::HeaderFormat,
+    label::StateRef,
+    macros::{Macro, MacroErr},
+    search_path::SearchPath,
+    source::SourcePos,
+    RieErr,
+};
+use std::{
+    collections::HashMap,
+    fs,
+    io::BufRead,
+    path::{Path, PathBuf},
+    sync::Arc,
+};
+
+/// Lines of code, along with where each came from.
+pub type Code = Vec<(SourcePos, String)>;
+
+/// Settings for the whole program, chosen by directives.
+#[derive(Debug, Clone, Default)]
+pub struct Settings {
+    /// What undefined entries should do, and where that was chosen.
+    pub fill: Option<(SourcePos, FillPolicy)>,
+    /// The state and arg the program starts at, and where they were chosen.
+    pub entry: Option<(SourcePos, StateRef, bool)>,
+}
+
+/// Gathers every line of code in a program,
+/// following includes and expanding macros along the way.
+pub struct Loader<'a> {
+    search_path: &'a SearchPath,
+    header: Option<HeaderFormat>,
+    include_stack: Vec<PathBuf>,
+    macros: HashMap<String, Macro>,
+    /// The macro whose body is being read, if any.
+    recording: Option<Macro>,
+    expansions: usize,
+    code: Code,
+    settings: Settings,
+}
+
+impl<'a> Loader<'a> {
+    pub fn new(search_path: &'a SearchPath) -> Self {
+        Self {
+            search_path,
+            header: None,
+            include_stack: vec![],
+            macros: HashMap::new(),
+            recording: None,
+            expansions: 0,
+            code: vec![],
+            settings: Settings::default(),
+        }
+    }
+
+    /// Returns the header of the program, every line of code in it, and the settings its directives chose.
+    pub fn load_root(
+        mut self,
+        reader: impl BufRead,
+        file: Arc<Path>,
+    ) -> Result<(HeaderFormat, Code, Settings), RieErr> {
+        self.include_stack
+            .push(fs::canonicalize(&file).unwrap_or(file.to_path_buf()));
+        self.load(reader, file, None)?;
+        let header = self.header.expect("A loaded program always has a header.");
+        Ok((header, self.code, self.settings))
+    }
+
+    /// Reads the header and every line of code in a file.
+    /// Included files must have a header that matches the header of the program.
+    fn load(
+        &mut self,
+        reader: impl BufRead,
+        file: Arc<Path>,
+        included_at: Option<&SourcePos>,
+    ) -> Result<(), RieErr> {
+        use RieErr::*;
+
+        let mut lines = reader.lines().enumerate().filter_map(|(i, line)| {
+            line.as_ref()
+                .is_ok_and(|line| line.starts_with(|c: char| c.is_whitespace()))
+                .then_some((SourcePos::new(&file, i + 1), line))
+        });
+
+        // Read and validate primary headers
+        let (pos, line) = lines.next().ok_or_else(|| NoHeader(file.to_path_buf()))?;
+        let header = line?
+            .parse::<HeaderFormat>()
+            .map_err(|e| BadHeader(pos, e))?;
+        match (&self.header, included_at) {
+            (Some(expected), Some(include_pos)) => {
+                if !expected.is_compatible(&header) {
+                    return Err(IncompatibleHeader(
+                        include_pos.clone(),
+                        file.to_path_buf(),
+                        expected.to_string(),
+                        header.to_string(),
+                    ));
+                }
+            }
+            _ => self.header = Some(header),
+        }
+
+        for (pos, line) in lines {
+            let line = line?;
+            if line.starts_with('\t') {
+                self.line(pos, line, 0)?;
+            }
+        }
+
+        // macros can't continue past the end of the file they started in
+        match self.recording.take() {
+            Some(unclosed) => Err(BadMacro(unclosed.pos, MacroErr::Unclosed(unclosed.name))),
+            None => Ok(()),
+        }
+    }
+
+    /// Handles one line of code, which may be a directive.
+    /// `depth` is how many macros deep the line is.
+    fn line(&mut self, pos: SourcePos, line: String, depth: usize) -> Result<(), RieErr> {
+        use RieErr::*;
+
+        let directive = if Directive::is_directive(&line) {
+            Some(Directive::parse(&line).map_err(|e| BadDirective(pos.clone(), e))?)
+        } else {
+            None
+        };
+
+        if self.recording.is_some() {
+            match directive {
+                Some(Directive::End) => {
+                    let finished = self.recording.take().unwrap();
+                    if let Some(existing) = self.macros.get(&finished.name) {
+                        let e = MacroErr::Redefined(finished.name, existing.pos.clone());
+                        return Err(BadMacro(pos, e));
+                    }
+                    self.macros.insert(finished.name.clone(), finished);
+                }
+                Some(Directive::Macro { .. }) => return Err(BadMacro(pos, MacroErr::Nested)),
+                _ => self.recording.as_mut().unwrap().body.push((pos, line)),
+            }
+            return Ok(());
+        }
+
+        match directive {
+            None => self.code.push((pos, line)),
+            Some(Directive::Include(name)) => self.include(pos, &name)?,
+            Some(Directive::Macro { name, params }) => {
+                let params = params
+                    .into_iter()
+                    .map(|param| param.trim_start_matches(Macro::PARAM_PREFIX).to_owned())
+                    .collect::<Vec<_>>();
+                if let Some(bad) = [&name]
+                    .into_iter()
+                    .chain(&params)
+                    .find(|name| !Macro::is_name(name))
+                {
+                    return Err(BadMacro(pos, MacroErr::BadName(bad.to_owned())));
+                }
+                self.recording = Some(Macro {
+                    name,
+                    params,
+                    pos,
+                    body: vec![],
+                });
+            }
+            Some(Directive::End) => return Err(BadMacro(pos, MacroErr::StrayEnd)),
+            Some(Directive::Fill(policy)) => {
+                if let Some((first, _policy)) = &self.settings.fill {
+                    let e = DirectiveErr::Repeated(Directive::FILL, first.clone());
+                    return Err(BadDirective(pos, e));
+                }
+                self.settings.fill = Some((pos, policy));
+            }
+            Some(Directive::Entry(state, arg)) => {
+                if let Some((first, ..)) = &self.settings.entry {
+                    let e = DirectiveErr::Repeated(Directive::ENTRY, first.clone());
+                    return Err(BadDirective(pos, e));
+                }
+                self.settings.entry = Some((pos, state, arg));
+            }
+            Some(Directive::Use { name, args }) => {
+                if depth >= Macro::MAX_DEPTH {
+                    return Err(BadMacro(pos.outermost().clone(), MacroErr::TooDeep(name)));
+                }
+                let used = self
+                    .macros
+                    .get(&name)
+                    .ok_or_else(|| BadMacro(pos.clone(), MacroErr::Undefined(name)))?;
+                self.expansions += 1;
+                let expansion = used
+                    .expand(&args, self.expansions, &pos)
+                    .map_err(|e| BadMacro(pos.clone(), e))?;
+                for (pos, line) in expansion {
+                    self.line(pos, line, depth + 1)?;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Loads a file named in an include directive at `pos`.
+    fn include(&mut self, pos: SourcePos, name: &str) -> Result<(), RieErr> {
+        use RieErr::*;
+
+        let included = self
+            .search_path
+            .resolve(name, pos.file.parent())
+            .map_err(|e| BadInclude(pos.clone(), Box::new(e)))?;
+        let canonical = fs::canonicalize(&included).unwrap_or(included.clone());
+        if self.include_stack.contains(&canonical) {
+            return Err(IncludeCycle(pos, included));
+        }
+        let source = fs::read_to_string(&included)
+            .map_err(|e| BadInclude(pos.clone(), Box::new(ReadErr(included.clone(), e))))?;
+
+        self.include_stack.push(canonical);
+        self.load(source.as_bytes(), Arc::from(included), Some(&pos))?;
+        self.include_stack.pop();
+        Ok(())
+    }
+}
@@ -0,0 +1,202 @@
+use super::{register_cmd::RegisterCmd, tm_cmd::TMCmd, RieProgram};
+use std::{fmt::Display, str::FromStr};
+
+/// A register's contents: an unbounded tape of bits with a head.
+#[derive(Debug, Clone, Default)]
+pub struct Tape {
+    /// Cells 0, 1, 2, ...
+    right: Vec<bool>,
+    /// Cells -1, -2, -3, ...
+    left: Vec<bool>,
+    head: i64,
+}
+
+impl Tape {
+    fn cell(&mut self, pos: i64) -> &mut bool {
+        let (cells, index) = if pos >= 0 {
+            (&mut self.right, pos as usize)
+        } else {
+            (&mut self.left, (-pos - 1) as usize)
+        };
+        if index >= cells.len() {
+            cells.resize(index + 1, false);
+        }
+        &mut cells[index]
+    }
+
+    pub fn get(&self, pos: i64) -> bool {
+        let (cells, index) = if pos >= 0 {
+            (&self.right, pos as usize)
+        } else {
+            (&self.left, (-pos - 1) as usize)
+        };
+        cells.get(index).copied().unwrap_or(false)
+    }
+
+    pub fn read(&self) -> bool {
+        self.get(self.head)
+    }
+
+    /// Applies a register command, returning the bit that was read if it was a Read.
+    pub fn apply(&mut self, cmd: RegisterCmd) -> Option<bool> {
+        use RegisterCmd::*;
+        match cmd {
+            Noop => {}
+            Push => self.head += 1,
+            Pull => self.head -= 1,
+            Flip => *self.cell(self.head) ^= true,
+            FlipPull => {
+                *self.cell(self.head) ^= true;
+                self.head -= 1;
+            }
+            Read => return Some(self.read()),
+        }
+        None
+    }
+
+    /// The range of cells that have been touched or are under the head.
+    pub fn bounds(&self) -> (i64, i64) {
+        let low = -(self.left.len() as i64);
+        let high = self.right.len() as i64 - 1;
+        (low.min(self.head), high.max(self.head))
+    }
+}
+
+impl Display for Tape {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let (low, high) = self.bounds();
+        for pos in low..=high {
+            let bit = self.get(pos) as u8;
+            if pos == self.head {
+                write!(f, "[{bit}]")?;
+            } else {
+                write!(f, "{bit}")?;
+            }
+        }
+        Ok(())
+    }
+}
+
+impl FromStr for Tape {
+    type Err = ();
+
+    /// Reads a tape the way it's displayed, like `10[1]1`, with the head on the bit in brackets.
+    /// Without brackets, the head is on the first bit.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut bits = vec![];
+        let mut head = None;
+        let mut chars = s.chars();
+        while let Some(c) = chars.next() {
+            match c {
+                '0' | '1' => bits.push(c == '1'),
+                '[' if head.is_none() => {
+                    head = Some(bits.len());
+                    match (chars.next(), chars.next()) {
+                        (Some(bit @ ('0' | '1')), Some(']')) => bits.push(bit == '1'),
+                        _ => return Err(()),
+                    }
+                }
+                _ => return Err(()),
+            }
+        }
+        if bits.is_empty() {
+            return Err(());
+        }
+
+        let head = head.unwrap_or(0) as i64;
+        let mut tape = Self::default();
+        for (i, bit) in bits.into_iter().enumerate() {
+            *tape.cell(i as i64 - head) = bit;
+        }
+        Ok(tape)
+    }
+}
+
+/// A simulated Read-If/Else machine.
+#[derive(Debug, Clone)]
+pub struct Machine {
+    pub state: u32,
+    pub arg: bool,
+    pub registers: Vec<Tape>,
+    pub steps: u64,
+    pub halted: bool,
+}
+
+impl Machine {
+    pub fn new(program: &RieProgram) -> Self {
+        Self {
+            state: 0,
+            arg: false,
+            registers: vec![Tape::default(); program.register_count()],
+            steps: 0,
+            halted: false,
+        }
+    }
+
+    /// The command that will be run on the next step.
+    pub fn current<'a>(&self, program: &'a RieProgram) -> &'a TMCmd {
+        &program.commands[self.state as usize][self.arg as usize]
+    }
+
+    /// Runs one command. A command that reads nothing stops the machine.
+    pub fn step(&mut self, program: &RieProgram) {
+        if self.halted {
+            return;
+        }
+        let cmd = self.current(program);
+        let mut read = cmd.read;
+        for (register, &register_cmd) in self.registers.iter_mut().zip(&cmd.register_cmds) {
+            if let Some(bit) = register.apply(register_cmd) {
+                read = Some(bit);
+            }
+        }
+        self.steps += 1;
+        self.state = cmd.goto;
+        match read {
+            Some(arg) => self.arg = arg,
+            None => self.halted = true,
+        }
+    }
+
+    /// Runs until the machine halts or `max_steps` commands have run in total.
+    pub fn run(&mut self, program: &RieProgram, max_steps: u64) {
+        while !self.halted && self.steps < max_steps {
+            self.step(program);
+        }
+    }
+
+    /// Every register, the way `rieasm run` shows them.
+    pub fn show_registers(&self, program: &RieProgram) -> String {
+        let mut out = String::new();
+        for (i, register) in self.registers.iter().enumerate() {
+            let name = program.register_names().describe(i);
+            out.push_str(&format!("    {name}: {register}\n"));
+        }
+        out
+    }
+
+    /// What `rieasm run` prints once it stops: whether the machine halted, and every register.
+    pub fn report(&self, program: &RieProgram) -> String {
+        let status = if self.halted {
+            format!(
+                "Halted after {} steps, at state {}.\n",
+                self.steps, self.state
+            )
+        } else {
+            format!(
+                "Still running after {} steps, at state {} with arg {}.\n",
+                self.steps, self.state, self.arg
+            )
+        };
+        status + &self.show_registers(program)
+    }
+}
+
+/// Runs a program from the start until it halts, failing the test if it doesn't.
+#[cfg(test)]
+pub(crate) fn run_to_halt(program: &RieProgram) -> Machine {
+    let mut machine = Machine::new(program);
+    machine.run(program, 1_000_000);
+    assert!(machine.halted, "The program didn't halt.");
+    machine
+}
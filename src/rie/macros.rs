@@ -0,0 +1,131 @@
+use super::{label::StateRef, source::SourcePos};
+use std::sync::Arc;
+use thiserror::Error;
+
+#[derive(Error, Debug)]
+pub enum MacroErr {
+    #[error(
+        "There is no macro named {0:?}.\n\
+        Macros must be defined before they are used."
+    )]
+    Undefined(String),
+
+    #[error("The macro {0:?} was already defined at {1}.")]
+    Redefined(String, SourcePos),
+
+    #[error("The macro {0:?} takes {1} arguments ({2}), but was given {3}.")]
+    WrongArgCount(String, usize, String, usize),
+
+    #[error("The macro {0:?} has no parameter named {1:?}.")]
+    UnknownParam(String, String),
+
+    #[error("{0:?} can't be the name of a macro or parameter. Names must be made of letters, digits and `_`.")]
+    BadName(String),
+
+    #[error("The macro {0:?} is never closed with `end`.")]
+    Unclosed(String),
+
+    #[error("Found an `end` outside of any macro.")]
+    StrayEnd,
+
+    #[error("Macros cannot be defined inside other macros.")]
+    Nested,
+
+    #[error(
+        "Macros were used inside macros more than {} times while expanding {0:?}.\n\
+        Does it use itself?",
+        Macro::MAX_DEPTH
+    )]
+    TooDeep(String),
+}
+
+/// A block of code that gets pasted in wherever it is used, with its parameters replaced.
+#[derive(Debug, Clone)]
+pub struct Macro {
+    pub name: String,
+    pub params: Vec<String>,
+    pub pos: SourcePos,
+    pub body: Vec<(SourcePos, String)>,
+}
+
+impl Macro {
+    pub const PARAM_PREFIX: char = '$';
+    pub const MAX_DEPTH: usize = 64;
+
+    pub fn is_name(name: &str) -> bool {
+        !name.is_empty() && name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_')
+    }
+
+    /// Gives every local label (a word starting with `.`) a name unique to expansion number `id`,
+    /// so that using a macro twice doesn't define its labels twice.
+    fn rename_locals(&self, line: &str, id: usize) -> String {
+        let mut out = String::new();
+        let mut word_start = true;
+        let mut chars = line.chars().peekable();
+        while let Some(c) = chars.next() {
+            let starts_label = word_start
+                && c == StateRef::LOCAL_PREFIX
+                && chars
+                    .peek()
+                    .is_some_and(|&next| next.is_ascii_alphabetic() || next == '_');
+            if starts_label {
+                out.push_str(&format!("{}#{id}", self.name));
+            }
+            out.push(c);
+            word_start = c.is_whitespace();
+        }
+        out
+    }
+
+    /// Replaces every `$param` in a line with its argument.
+    fn substitute(&self, line: &str, args: &[String]) -> Result<String, MacroErr> {
+        let mut out = String::new();
+        let mut rest = line;
+        while let Some(start) = rest.find(Self::PARAM_PREFIX) {
+            out.push_str(&rest[..start]);
+            let after = &rest[start + 1..];
+            let end = after
+                .find(|c: char| !(c.is_ascii_alphanumeric() || c == '_'))
+                .unwrap_or(after.len());
+            let param = &after[..end];
+            let index = self
+                .params
+                .iter()
+                .position(|p| p == param)
+                .ok_or_else(|| MacroErr::UnknownParam(self.name.clone(), param.to_owned()))?;
+            out.push_str(&args[index]);
+            rest = &after[end..];
+        }
+        out.push_str(rest);
+        Ok(out)
+    }
+
+    /// The lines of code this macro turns into when used at `use_pos` with `args`.
+    pub fn expand(
+        &self,
+        args: &[String],
+        id: usize,
+        use_pos: &SourcePos,
+    ) -> Result<Vec<(SourcePos, String)>, MacroErr> {
+        if args.len() != self.params.len() {
+            return Err(MacroErr::WrongArgCount(
+                self.name.clone(),
+                self.params.len(),
+                self.params.join(", "),
+                args.len(),
+            ));
+        }
+        let use_pos = Arc::new(use_pos.clone());
+        self.body
+            .iter()
+            .map(|(pos, line)| {
+                let line = self.substitute(&self.rename_locals(line, id), args)?;
+                let pos = SourcePos {
+                    expanded_from: Some(Arc::clone(&use_pos)),
+                    ..pos.clone()
+                };
+                Ok((pos, line))
+            })
+            .collect()
+    }
+}
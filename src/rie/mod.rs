@@ -1,18 +1,43 @@
+pub mod allocator;
+pub mod assembler;
+pub mod c_source;
+pub mod control_flow;
+pub mod directive;
+pub mod disasm;
+pub mod formatter;
 pub mod header;
+pub mod image;
+pub mod json;
+pub mod label;
+pub mod listing;
+pub mod loader;
+pub mod machine;
+pub mod macros;
+pub mod optimize;
 pub mod register_cmd;
 pub mod rie_line;
+pub mod search_path;
+pub mod source;
+pub mod source_map;
 pub mod tm_cmd;
+pub mod verilog;
 
 use self::{
-    header::{HeaderErr, HeaderFormat},
+    assembler::{Assembler, FillPolicy},
+    control_flow::ControlFlowErr,
+    directive::DirectiveErr,
+    header::{HeaderErr, RegisterNames},
+    label::StateRef,
+    macros::MacroErr,
     rie_line::{RieLine, RieLineErr},
+    source::{unnamed_source, SourcePos},
     tm_cmd::TMCmd,
 };
-use crate::helpers::{ask_y_n, break_string, extend_vec_to, largest_bit, RESET, YELLOW};
+use crate::helpers::break_string;
 use std::{
     fmt::Display,
-    fs::File,
-    io::{self, BufRead, BufReader},
+    io::{self, BufRead, Write},
+    path::PathBuf,
 };
 
 use thiserror::Error;
@@ -22,35 +47,177 @@ pub enum RieErr {
     #[error("IO Error: {0}")]
     IO(#[from] io::Error),
 
-    #[error("The specified flipifelse file has no header.")]
-    NoHeader,
+    #[error(
+        "Could not find {0}. I looked for it in:\n\
+        {}",
+        .1.iter().map(|path| format!("    {}", path.display())).collect::<Vec<_>>().join("\n"),
+    )]
+    FileNotFound(String, Vec<PathBuf>),
 
-    #[error("{0}")]
-    BadHeader(#[from] HeaderErr),
+    #[error("Could not read {}: {1}", .0.display())]
+    ReadErr(PathBuf, io::Error),
 
-    #[error("Error on line {0}: {1}")]
-    BadLine(usize, RieLineErr),
+    #[error("{} has no header.", .0.display())]
+    NoHeader(PathBuf),
 
-    #[error("Cancelled compilation.")]
-    Cancelled,
+    #[error("Bad header at {0}: {1}")]
+    BadHeader(SourcePos, HeaderErr),
+
+    #[error("Error at {0}: {1}")]
+    BadLine(SourcePos, RieLineErr),
+
+    #[error("Error at {0}: {1}")]
+    BadControlFlow(SourcePos, ControlFlowErr),
+
+    #[error("Error at {0}: {1}")]
+    BadDirective(SourcePos, DirectiveErr),
+
+    #[error("Error at {0}: {1}")]
+    BadMacro(SourcePos, MacroErr),
+
+    #[error(
+        "Error at {0}: There is no state labelled {1:?}.\n\
+        Labels are defined by using them as the 'state' of a line."
+    )]
+    UndefinedLabel(SourcePos, String),
+
+    #[error("Error at {0}: {1} would be State {2}, which can't exist.")]
+    BadOffset(SourcePos, StateRef, i64),
+
+    #[error(
+        "Error at {0}: {1} lands on State {2}, which is the state of the label {3:?}.\n\
+        Labels are numbered by the assembler, so an offset from one can't be used to reach another. Use {3} instead."
+    )]
+    OffsetOntoLabel(SourcePos, StateRef, u32, String),
+
+    #[error(
+        "Error at {0}: State {2} with Arg {3} was already defined at {1}.\n\
+        Every state can only have one line for each arg, and a wildcard arg counts as both."
+    )]
+    DuplicateDefinition(SourcePos, SourcePos, StateRef, bool),
+
+    #[error("Could not include the file at {0}: {1}")]
+    BadInclude(SourcePos, Box<RieErr>),
+
+    #[error("{0} includes {}, which is already being included.", .1.display())]
+    IncludeCycle(SourcePos, PathBuf),
+
+    #[error(
+        "{0} includes {}, which has an incompatible header.\n\
+        Expected: {2}\n\
+        Found:    {3}\n\
+        Included files must have the same registers as the file that includes them.",
+        .1.display(),
+    )]
+    IncompatibleHeader(SourcePos, PathBuf, String, String),
+
+    #[error(
+        "{1}, first used at {0}, can't share any physical register.\n\
+        Registers can only share one if they're never in use at the same time,\n\
+        and each is left blank wherever the other is in use.\n\
+        Add another reg column, or clear the registers it could share with once they're done."
+    )]
+    NoRoomForRegister(SourcePos, String),
+}
+
+/// Something suspicious about a program that still compiles fine.
+#[derive(Error, Debug, Clone, PartialEq, Eq)]
+pub enum RieWarning {
+    #[error(
+        "The program is trying to create at least {total_cmds} instructions.\n\
+        This is coming from {pos}, which specifies that state {state} must goto {goto}."
+    )]
+    LargeProgram {
+        pos: SourcePos,
+        state: u32,
+        goto: u32,
+        total_cmds: usize,
+    },
+}
+
+// how the cells of the ROM are spaced out in the RLE
+const COLUMN_SPACING: usize = 3;
+const PAIR_SPACING: usize = 4;
+const ROW_SPACING: usize = 3;
+const SEGMENT_SPACING: usize = 5;
+const ROW_OFFSET: usize = 1;
+
+/// Where [`RieProgram::rle`] puts the cell for `row` (counting every segment before it) of the column for `address`,
+/// where the row is in segment number `segment`.
+fn cell_position(address: usize, row: usize, segment: usize) -> (usize, usize) {
+    let (state, arg) = (address / 2, address % 2);
+    let x = row * ROW_OFFSET
+        + state * (1 + COLUMN_SPACING + 1 + PAIR_SPACING)
+        + arg * (1 + COLUMN_SPACING);
+    let y = row * (1 + ROW_SPACING) + segment * (SEGMENT_SPACING - ROW_SPACING);
+    (x, y)
 }
 
 pub struct RieProgram {
     commands: Vec<[TMCmd; 2]>,
     state_bits: u32,
-    register_count: usize,
+    register_names: RegisterNames,
+    /// The virtual registers the assembler put in each register, which expect it to start out blank.
+    virtual_registers: Vec<Vec<String>>,
+    /// How many states the assembler added to split up lines that take more than one step.
+    split_states: usize,
+    /// The line each command came from, if it came from one.
+    sources: Vec<[Option<SourcePos>; 2]>,
+    /// What entries that no line defines do, which the optimizer also does with entries it can't reach.
+    fill: FillPolicy,
 }
 impl RieProgram {
     pub fn len(&self) -> usize {
         self.commands.len() * 2
     }
 
+    pub fn is_empty(&self) -> bool {
+        self.commands.is_empty()
+    }
+
+    pub fn state_bits(&self) -> u32 {
+        self.state_bits
+    }
+
+    pub fn register_count(&self) -> usize {
+        self.register_names.0.len()
+    }
+
+    pub fn register_names(&self) -> &RegisterNames {
+        &self.register_names
+    }
+
+    /// The virtual registers put in a register, which expect it to start out blank.
+    pub fn virtual_registers(&self, register: usize) -> &[String] {
+        self.virtual_registers
+            .get(register)
+            .map_or(&[], |names| names.as_slice())
+    }
+
+    pub fn split_states(&self) -> usize {
+        self.split_states
+    }
+
+    pub fn fill(&self) -> FillPolicy {
+        self.fill
+    }
+
+    /// The line that the command at `state` with `arg` came from, if it came from one.
+    pub fn source(&self, state: usize, arg: bool) -> Option<&SourcePos> {
+        self.sources.get(state)?[arg as usize].as_ref()
+    }
+
+    /// How many decimal digits it takes to write the largest state.
+    pub fn state_digits(&self) -> usize {
+        ((1 << self.state_bits) as f32).log10() as usize + 1
+    }
+
     pub fn assemble(&self) -> Vec<[Vec<Vec<bool>>; 2]> {
         self.commands
             .iter()
             .map(|pair| {
                 pair.clone()
-                    .map(|cmd| cmd.assemble(self.state_bits, self.register_count))
+                    .map(|cmd| cmd.assemble(self.state_bits, self.register_count()))
             })
             .collect()
     }
@@ -82,12 +249,6 @@ impl RieProgram {
         let row_count = row;
         let segment_count = first_column.len();
 
-        const COLUMN_SPACING: usize = 3;
-        const PAIR_SPACING: usize = 4;
-        const ROW_SPACING: usize = 3;
-        const SEGMENT_SPACING: usize = 5;
-        const ROW_OFFSET: usize = 1;
-
         // extend each respective segment
         for pair in column_pairs.iter() {
             for (arg, column) in pair.iter().enumerate() {
@@ -130,91 +291,21 @@ impl RieProgram {
     }
 }
 
-impl TryFrom<File> for RieProgram {
-    type Error = RieErr;
-
-    fn try_from(value: File) -> Result<Self, Self::Error> {
-        use RieErr::*;
-
-        eprintln!("Compiling to IR...");
-
-        let mut lines = BufReader::new(value)
-            .lines()
-            .enumerate()
-            .filter_map(|(i, line)| {
-                line.as_ref()
-                    .map_or(false, |line| line.starts_with(|c: char| c.is_whitespace()))
-                    .then(|| (i + 1, line))
-            });
-
-        // Read and validate primary headers
-        let (_i, line) = lines.next().ok_or(NoHeader)?;
-        let HeaderFormat { register_count } = line?.parse::<HeaderFormat>()?;
-
-        let mut warning_size = 1 << 6;
-        // let mut highest_state = 0;
-        let mut commands = vec![];
-        let mut add_cmd = |line: usize,
-                           state: u32,
-                           arg: bool,
-                           tm_cmd: TMCmd|
-         -> Result<(), RieErr> {
-            let extension = state.max(tm_cmd.goto) as usize;
-
-            if extension > warning_size {
-                let total_cmds = 2 << largest_bit(extension);
-                eprintln!(
-                    "{YELLOW}WAIT!{RESET}\n\
-                    The program is trying to create at least {YELLOW}{total_cmds} instructions.{RESET}\n\
-                    This is coming from line {line}, which specifies that state {state} must goto {}.\n\
-                    Are you sure you want a machine with that many instructions? I will notify you if it goes beyond another power of two.",
-                    tm_cmd.goto
-                );
-                if !ask_y_n() {
-                    return Err(Cancelled);
-                }
-                eprintln!("Continuing...");
-                warning_size = total_cmds >> 1;
-            }
+impl RieProgram {
+    pub fn parse(source: &str) -> Result<(Self, Vec<RieWarning>), RieErr> {
+        Assembler::new().parse_str(source, unnamed_source())
+    }
 
-            /*
-            if highest_state > state {
-                eprintln!("{YELLOW}Warning: Lines out of order. Line {line} should probably come earlier.{RESET}");
-                pause();
-            }
-            highest_state = state;
-            */
-            extend_vec_to(
-                &mut commands,
-                [TMCmd::default(), TMCmd::default()],
-                extension + 1,
-            );
-            commands[state as usize][arg as usize] = tm_cmd;
-
-            Ok(())
-        };
-
-        for (i, line) in lines {
-            let line = line?;
-            if line.starts_with('\t') {
-                let RieLine { state, arg, cmd } =
-                    RieLine::parse(&line, register_count).map_err(|e| BadLine(i, e))?;
-                add_cmd(i, state, arg, cmd)?;
-            }
-        }
+    pub fn from_reader(reader: impl BufRead) -> Result<(Self, Vec<RieWarning>), RieErr> {
+        Assembler::new().parse_reader(reader, unnamed_source())
+    }
 
-        let state_bits = largest_bit(commands.len() - 1);
-        extend_vec_to(
-            &mut commands,
-            [TMCmd::default(), TMCmd::default()],
-            1 << state_bits,
-        );
+    pub fn write_rle(&self, mut out: impl Write) -> io::Result<()> {
+        writeln!(out, "{}", self.rle())
+    }
 
-        Ok(RieProgram {
-            commands,
-            register_count,
-            state_bits,
-        })
+    pub fn write_rie(&self, mut out: impl Write) -> io::Result<()> {
+        write!(out, "{}", self.to_rie())
     }
 }
 
@@ -225,15 +316,16 @@ impl Display for RieProgram {
             "{}-bit State ({}-bit Demux), {} Registers:",
             self.state_bits,
             self.state_bits + 1,
-            self.register_count
+            self.register_count()
         )?;
-        let state_digits = ((1 << self.state_bits) as f32).log10() as usize + 1;
+        let state_digits = self.state_digits();
+        let names = &self.register_names;
         for (state, [cmd0, cmd1]) in self.commands.iter().enumerate() {
             writeln!(
                 f,
                 "{}\n{}",
-                RieLine::to_string(state_digits, state as u32, false, cmd0),
-                RieLine::to_string(state_digits, state as u32, true, cmd1),
+                RieLine::to_string(state_digits, names, state as u32, false, cmd0),
+                RieLine::to_string(state_digits, names, state as u32, true, cmd1),
             )?;
         }
         Ok(())
@@ -0,0 +1,469 @@
+use super::{assembler::FillPolicy, register_cmd::RegisterCmd, tm_cmd::TMCmd, RieProgram};
+use crate::helpers::{extend_vec_to, largest_bit};
+use std::{collections::BTreeMap, fmt::Display};
+
+/// What the optimizer did to a program.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct Optimizations {
+    /// `%` followed by `<` on the same register, turned into `%<`.
+    pub fused: usize,
+    /// Commands on different registers in consecutive states, done in one step instead.
+    pub merged: usize,
+    /// Jumps to states that do nothing but go somewhere else, which now go straight there.
+    pub bypassed: usize,
+    /// Commands that can no longer be reached, which now do what the fill policy says.
+    pub removed: usize,
+    /// States that did the same thing as an earlier state, which now share its number.
+    pub shared: usize,
+}
+
+impl Display for Optimizations {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "Fused {} commands into `%<`, merged {} steps, bypassed {} trampolines, removed {} unreachable commands, \
+            and shared {} equivalent states.",
+            self.fused, self.merged, self.bypassed, self.removed, self.shared
+        )
+    }
+}
+
+/// What happened when two commands were combined into one.
+enum Combined {
+    Fused,
+    Merged,
+    Bypassed,
+}
+
+/// The commands of running `first` and then `then`, in one step if the hardware can do that.
+fn combine(first: &[RegisterCmd], then: &[RegisterCmd]) -> Option<(Vec<RegisterCmd>, Combined)> {
+    use RegisterCmd::*;
+
+    let mut combined = Combined::Bypassed;
+    let mut register_cmds = vec![];
+    for register in 0..first.len().max(then.len()) {
+        let first = first.get(register).copied().unwrap_or_default();
+        let then = then.get(register).copied().unwrap_or_default();
+        let cmd = match (first, then) {
+            (cmd, Noop) => cmd,
+            (Noop, cmd) => {
+                combined = match combined {
+                    Combined::Bypassed => Combined::Merged,
+                    other => other,
+                };
+                cmd
+            }
+            (Flip, Pull) => {
+                combined = Combined::Fused;
+                FlipPull
+            }
+            _ => return None,
+        };
+        register_cmds.push(cmd);
+    }
+    Some((register_cmds, combined))
+}
+
+impl RieProgram {
+    /// The entries (`state * 2 + arg`) that can be run, starting from state 0 with arg false.
+    fn reachable(&self) -> Vec<bool> {
+        let mut seen = vec![false; self.commands.len() * 2];
+        let mut stack = vec![0];
+        while let Some(entry) = stack.pop() {
+            if seen[entry] {
+                continue;
+            }
+            seen[entry] = true;
+            let cmd = &self.commands[entry / 2][entry % 2];
+            let next = cmd.goto as usize * 2;
+            match cmd.read {
+                Some(bit) => stack.push(next + bit as usize),
+                None if cmd.register_cmds.contains(&RegisterCmd::Read) => {
+                    stack.extend([next, next + 1])
+                }
+                None => {}
+            }
+        }
+        seen
+    }
+
+    /// Whether the entry at `state` with `arg` does what the fill policy fills undefined entries with.
+    fn is_fill(&self, state: usize, arg: bool) -> bool {
+        let cmd = &self.commands[state][arg as usize];
+        let does_nothing = cmd
+            .register_cmds
+            .iter()
+            .all(|&cmd| cmd == RegisterCmd::Noop);
+        // a trap is a state that goes to itself with arg false, whatever the arg
+        let is_trap = |state: u32| {
+            self.commands[state as usize].iter().all(|cmd| {
+                cmd.goto == state
+                    && cmd.read == Some(false)
+                    && cmd
+                        .register_cmds
+                        .iter()
+                        .all(|&cmd| cmd == RegisterCmd::Noop)
+            })
+        };
+        match self.fill {
+            FillPolicy::Halt => cmd.is_halt(),
+            FillPolicy::Trap => does_nothing && cmd.read == Some(false) && is_trap(cmd.goto),
+            FillPolicy::Loop => does_nothing && cmd.goto as usize == state && cmd.read == Some(arg),
+        }
+    }
+
+    /// Fills every entry that can't be reached the way the assembler fills undefined entries,
+    /// adding a state for traps to go to if every state can be reached.
+    fn refill(&mut self) {
+        if self.fill == FillPolicy::Halt {
+            return;
+        }
+        let reachable = self.reachable();
+        let is_reachable = |state: usize, arg: bool| {
+            reachable
+                .get(state * 2 + arg as usize)
+                .copied()
+                .unwrap_or(false)
+        };
+        let trap = (0..)
+            .find(|&state| !is_reachable(state, false) && !is_reachable(state, true))
+            .expect("A program can't reach every state.");
+        if self.fill == FillPolicy::Trap && trap >= self.commands.len() {
+            self.state_bits = largest_bit(trap);
+            extend_vec_to(
+                &mut self.commands,
+                [TMCmd::default(), TMCmd::default()],
+                1 << self.state_bits,
+            );
+            extend_vec_to(&mut self.sources, [None, None], 1 << self.state_bits);
+        }
+        for (state, pair) in self.commands.iter_mut().enumerate() {
+            for (arg, cmd) in [false, true].into_iter().zip(pair) {
+                if !is_reachable(state, arg) {
+                    *cmd = self.fill.fill(state as u32, arg, trap as u32);
+                }
+            }
+        }
+    }
+
+    /// Gives states that behave the same the same number, like minimizing a DFA:
+    /// two states are the same if they run the same commands, read the same way,
+    /// and go to states that are the same, for both args.
+    /// The states are then renumbered in order, keeping state 0 first.
+    ///
+    /// Returns how many reachable states were merged into others.
+    fn share_equivalent_states(&mut self) -> usize {
+        // an entry, as far as comparing states goes
+        type Entry = (Vec<RegisterCmd>, Option<bool>, usize);
+
+        // start with every state in one group, and split groups until nothing changes
+        let mut groups = vec![0; self.commands.len()];
+        let mut group_count = 1;
+        loop {
+            let mut ids = BTreeMap::<[Entry; 2], usize>::new();
+            let next_groups = self
+                .commands
+                .iter()
+                .map(|pair| {
+                    let key = pair.clone().map(|cmd| {
+                        let mut register_cmds = cmd.register_cmds;
+                        while register_cmds.last() == Some(&RegisterCmd::Noop) {
+                            register_cmds.pop();
+                        }
+                        (register_cmds, cmd.read, groups[cmd.goto as usize])
+                    });
+                    let next_id = ids.len();
+                    *ids.entry(key).or_insert(next_id)
+                })
+                .collect::<Vec<_>>();
+            groups = next_groups;
+            // groups only ever split, so the same count means the same groups
+            if ids.len() == group_count {
+                break;
+            }
+            group_count = ids.len();
+        }
+
+        // number the groups by their first reachable state, so unreachable ones end up last
+        let reachable = self.reachable();
+        let is_reachable = |state: usize| reachable[state * 2] || reachable[state * 2 + 1];
+        let mut numbers = vec![None; group_count];
+        let mut order = vec![];
+        let states = (0..self.commands.len())
+            .filter(|&state| is_reachable(state))
+            .chain((0..self.commands.len()).filter(|&state| !is_reachable(state)));
+        for state in states {
+            let number = &mut numbers[groups[state]];
+            if number.is_none() {
+                *number = Some(order.len());
+                order.push(state);
+            }
+        }
+        let reachable_states = (0..self.commands.len())
+            .filter(|&state| is_reachable(state))
+            .count();
+        let reachable_groups = order.iter().filter(|&&state| is_reachable(state)).count();
+
+        let number_of =
+            |state: u32| numbers[groups[state as usize]].expect("Every group has a state.") as u32;
+        self.commands = order
+            .iter()
+            .map(|&state| {
+                self.commands[state].clone().map(|cmd| TMCmd {
+                    goto: number_of(cmd.goto),
+                    ..cmd
+                })
+            })
+            .collect();
+        self.sources = order
+            .iter()
+            .map(|&state| self.sources[state].clone())
+            .collect();
+
+        reachable_states - reachable_groups
+    }
+
+    /// Runs the commands that a command with a literal read goes to in the same step, as long as the hardware can.
+    /// This fuses `%` and `<` into `%<`, merges commands on different registers,
+    /// and skips over states that only go somewhere else.
+    /// Commands that can no longer be reached are replaced with what the program's fill policy puts in undefined entries,
+    /// and the program shrinks if its last states are no longer needed.
+    pub fn optimize(&mut self) -> Optimizations {
+        let mut optimizations = Optimizations::default();
+
+        // every change moves a command further along its path, so this always settles down,
+        // but cycles of commands that do nothing get a limit just in case
+        let mut changed = true;
+        let mut passes = 0;
+        while changed && passes < self.len() {
+            changed = false;
+            passes += 1;
+            for entry in 0..self.len() {
+                let cmd = &self.commands[entry / 2][entry % 2];
+                let Some(arg) = cmd.read else {
+                    continue;
+                };
+                let next = cmd.goto as usize * 2 + arg as usize;
+                if next == entry {
+                    continue;
+                }
+                let then = &self.commands[next / 2][next % 2];
+                // halting right away is kept, so the machine still says where it stopped
+                if then.is_halt() {
+                    continue;
+                }
+                // going to a command that loops in place, like a trap, is already as short as it gets
+                if then.read == Some(next % 2 == 1) && then.goto as usize == next / 2 {
+                    continue;
+                }
+                let Some((register_cmds, combined)) =
+                    combine(&cmd.register_cmds, &then.register_cmds)
+                else {
+                    continue;
+                };
+                match combined {
+                    Combined::Fused => optimizations.fused += 1,
+                    Combined::Merged => optimizations.merged += 1,
+                    Combined::Bypassed => optimizations.bypassed += 1,
+                }
+                self.commands[entry / 2][entry % 2] = TMCmd {
+                    goto: then.goto,
+                    read: then.read,
+                    register_cmds,
+                };
+                changed = true;
+            }
+        }
+
+        // unreachable entries halt for now, so they don't keep states around, and get filled in at the end
+        let reachable = self.reachable();
+        let unreachable = (0..self.len())
+            .filter(|&entry| !reachable[entry])
+            .map(|entry| (entry / 2, entry % 2 == 1))
+            .filter(|&(state, arg)| !self.commands[state][arg as usize].is_halt())
+            .map(|(state, arg)| (state, arg, self.is_fill(state, arg)))
+            .collect::<Vec<_>>();
+        for (state, arg, is_fill) in unreachable {
+            if !is_fill {
+                optimizations.removed += 1;
+            }
+            self.commands[state][arg as usize] = TMCmd::default();
+            self.sources[state][arg as usize] = None;
+        }
+        optimizations.shared = self.share_equivalent_states();
+
+        // the program only needs as many states as it still uses
+        let last_state = self
+            .commands
+            .iter()
+            .enumerate()
+            .flat_map(|(state, pair)| {
+                pair.iter()
+                    .filter(|cmd| !cmd.is_halt())
+                    .map(move |cmd| state.max(cmd.goto as usize))
+            })
+            .max()
+            .unwrap_or(0);
+        self.state_bits = largest_bit(last_state);
+        self.commands.truncate(1 << self.state_bits);
+        extend_vec_to(
+            &mut self.commands,
+            [TMCmd::default(), TMCmd::default()],
+            1 << self.state_bits,
+        );
+        self.sources.truncate(1 << self.state_bits);
+        extend_vec_to(&mut self.sources, [None, None], 1 << self.state_bits);
+        self.refill();
+
+        optimizations
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::rie::machine::{run_to_halt, Machine};
+
+    /// Checks that optimizing a program leaves its registers the same when it halts,
+    /// without taking any more steps.
+    fn runs_the_same(source: &str) -> Optimizations {
+        let (program, _warnings) = RieProgram::parse(source).unwrap();
+        let (mut optimized, _warnings) = RieProgram::parse(source).unwrap();
+        let optimizations = optimized.optimize();
+        let (before, after) = (run_to_halt(&program), run_to_halt(&optimized));
+        let registers = |machine: &Machine| {
+            machine
+                .registers
+                .iter()
+                .map(|tape| tape.to_string())
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(registers(&after), registers(&before));
+        assert!(after.steps <= before.steps);
+        optimizations
+    }
+
+    #[test]
+    fn fuses_flip_and_pull() {
+        let optimizations = runs_the_same(
+            "\tstate\targ\tgoto\tread\treg\treg
+\t0\tfalse\t1\tfalse\t%
+\t1\tfalse\t2\tfalse\t<
+\t2\tfalse\t3\tfalse\t%
+\t3\tfalse\t3
+",
+        );
+        assert_eq!(optimizations.fused, 1);
+    }
+
+    #[test]
+    fn merges_steps_on_different_registers() {
+        let optimizations = runs_the_same(
+            "\tstate\targ\tgoto\tread\treg\treg
+\t0\tfalse\t1\tfalse\t>
+\t1\tfalse\t2\tfalse\t\t%
+\t2\tfalse\t3\tfalse
+\t3\tfalse\t4\tfalse\t%
+\t4\tfalse\t4
+",
+        );
+        assert!(optimizations.merged > 0);
+    }
+
+    #[test]
+    fn keeps_loops_working() {
+        // moves 5 set cells from `a` to `b`, one at a time
+        runs_the_same(
+            "\tstate\targ\tgoto\tread\treg:a\treg:b
+\troutine 0 {
+\t\t%@a >@a %@a >@a %@a >@a %@a >@a %@a
+\t\twhile a? {
+\t\t\t%<@a
+\t\t\t%@b >@b
+\t\t}
+\t}
+",
+        );
+    }
+
+    #[test]
+    fn shares_equivalent_states() {
+        let source = "\tstate\targ\tgoto\tread\treg:a\treg:b
+\troutine 0 {
+\t\tif a? {
+\t\t\tgoto one
+\t\t} else {
+\t\t\tgoto two
+\t\t}
+\t}
+\troutine one {
+\t\t>@b %@b
+\t}
+\troutine two {
+\t\t>@b %@b
+\t}
+";
+        let optimizations = runs_the_same(source);
+        assert!(optimizations.shared > 0);
+
+        // the copies are gone, so both args of the state after the read do the same thing
+        let (mut program, _warnings) = RieProgram::parse(source).unwrap();
+        program.optimize();
+        let [if_false, if_true] = &program.commands[program.commands[0][0].goto as usize];
+        assert_eq!(
+            (if_false.goto, &if_false.register_cmds),
+            (if_true.goto, &if_true.register_cmds)
+        );
+    }
+
+    /// A program with a state that can't be reached, using a fill policy.
+    fn unreachable_state(fill: &str) -> RieProgram {
+        let source = format!(
+            "\tstate\targ\tgoto\tread\treg\n\
+            \tfill\t{fill}\n\
+            \t0\tfalse\t1\ttrue\t>\n\
+            \t1\ttrue\t0\t\t?\n\
+            \t5\tfalse\t5\t\t%\n"
+        );
+        RieProgram::parse(&source).unwrap().0
+    }
+
+    #[test]
+    fn keeps_the_fill_policy() {
+        for (name, fill) in FillPolicy::POLICIES {
+            let mut program = unreachable_state(name);
+            assert_eq!(program.fill(), fill);
+            let optimizations = program.optimize();
+            assert_eq!(optimizations.removed, 1);
+
+            let reachable = program.reachable();
+            for state in 0..program.commands.len() {
+                for arg in [false, true] {
+                    if !reachable[state * 2 + arg as usize] {
+                        assert!(program.is_fill(state, arg), "{name}: {state} {arg}");
+                    }
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn loops_in_place() {
+        let mut program = unreachable_state("loop");
+        program.optimize();
+        let cmd = &program.commands[1][0];
+        assert_eq!((cmd.goto, cmd.read), (1, Some(false)));
+    }
+
+    #[test]
+    fn traps_go_to_a_trap() {
+        let mut program = unreachable_state("trap");
+        program.optimize();
+        let trap = program.commands[1][0].goto as usize;
+        assert_ne!(trap, 0);
+        assert_ne!(trap, 1);
+        for cmd in &program.commands[trap] {
+            assert_eq!((cmd.goto as usize, cmd.read), (trap, Some(false)));
+        }
+    }
+}
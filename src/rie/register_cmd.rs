@@ -1,3 +1,4 @@
+use serde::{Deserialize, Serialize};
 use std::str::FromStr;
 use thiserror::Error;
 
@@ -9,8 +10,9 @@ use thiserror::Error;
 )]
 pub struct BadRegisterCmd(String);
 
-#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Default, Serialize, Deserialize)]
 pub enum RegisterCmd {
+    #[default]
     Noop,
     Push,
     Pull,
@@ -20,6 +22,9 @@ pub enum RegisterCmd {
 }
 
 impl RegisterCmd {
+    /// Separates a command from the register it is sent to, as in `%<@2`.
+    pub const ADDRESS_SEPARATOR: char = '@';
+
     pub(crate) const VALID_COMMANDS: [(&'static str, Self); 5] = [
         (">", Self::Push),
         ("<", Self::Pull),
@@ -28,6 +33,41 @@ impl RegisterCmd {
         ("?", Self::Read),
     ];
 
+    /// Splits a cell like `%>>` into the commands it runs one after another.
+    /// `%<` is always one command.
+    pub fn parse_sequence(s: &str) -> Result<Vec<Self>, BadRegisterCmd> {
+        let mut cmds = vec![];
+        let mut rest = s;
+        while !rest.is_empty() {
+            let &(token, cmd) = Self::VALID_COMMANDS
+                .iter()
+                .filter(|(token, _cmd)| rest.starts_with(token))
+                .max_by_key(|(token, _cmd)| token.len())
+                .ok_or_else(|| BadRegisterCmd(s.to_owned()))?;
+            cmds.push(cmd);
+            rest = &rest[token.len()..];
+        }
+        Ok(cmds)
+    }
+
+    /// Lines up the commands of every register into steps, with at most one command per register in each.
+    /// Reads happen in the last step, so that they decide where the machine goes next.
+    pub fn steps(sequences: &[Vec<Self>]) -> Vec<Vec<Self>> {
+        let len = sequences.iter().map(Vec::len).max().unwrap_or(0).max(1);
+        let mut steps = vec![vec![Self::Noop; sequences.len()]; len];
+        for (register, sequence) in sequences.iter().enumerate() {
+            let offset = if sequence.last() == Some(&Self::Read) {
+                len - sequence.len()
+            } else {
+                0
+            };
+            for (i, &cmd) in sequence.iter().enumerate() {
+                steps[offset + i][register] = cmd;
+            }
+        }
+        steps
+    }
+
     pub fn assemble(&self) -> [bool; 4] {
         use RegisterCmd::*;
         match self {
@@ -41,12 +81,6 @@ impl RegisterCmd {
     }
 }
 
-impl Default for RegisterCmd {
-    fn default() -> Self {
-        Self::Noop
-    }
-}
-
 impl FromStr for RegisterCmd {
     type Err = BadRegisterCmd;
 
@@ -54,8 +88,8 @@ impl FromStr for RegisterCmd {
         use RegisterCmd::*;
         Self::VALID_COMMANDS
             .iter()
-            .find_map(|&(token, command)| (s == token).then(|| command))
-            .or_else(|| s.is_empty().then(|| Noop))
+            .find_map(|&(token, command)| (s == token).then_some(command))
+            .or_else(|| s.is_empty().then_some(Noop))
             .ok_or_else(|| BadRegisterCmd(s.to_owned()))
     }
 }
@@ -1,60 +1,130 @@
 use super::{
+    header::RegisterNames,
+    label::StateRef,
     register_cmd::{BadRegisterCmd, RegisterCmd},
     tm_cmd::TMCmd,
 };
 use crate::helpers::{get_tokens, next_token};
+use std::{fmt::Display, str::FromStr};
 use thiserror::Error;
 
+/// The 'arg' of a line: either one arg, or a wildcard for both.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ArgPattern {
+    Only(bool),
+    Any,
+}
+
+impl ArgPattern {
+    pub const WILDCARDS: [&'static str; 2] = ["*", "any"];
+
+    /// Every arg the line defines a command for.
+    pub fn args(self) -> Vec<bool> {
+        match self {
+            Self::Only(arg) => vec![arg],
+            Self::Any => vec![false, true],
+        }
+    }
+}
+
+impl FromStr for ArgPattern {
+    type Err = <bool as FromStr>::Err;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if Self::WILDCARDS.contains(&s) {
+            return Ok(Self::Any);
+        }
+        s.parse().map(Self::Only)
+    }
+}
+
+impl Display for ArgPattern {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Only(arg) => write!(f, "{arg}"),
+            Self::Any => write!(f, "any"),
+        }
+    }
+}
+
 #[derive(Error, Debug)]
 pub enum RieLineErr {
     #[error("There was a tab, indicating a real line, but no state was specified.")]
     NoState,
 
-    #[error("Could not parse the 'state' ({0}).")]
+    #[error(
+        "Could not parse the 'state' ({0}).\n\
+        States must be non-negative integers or labels."
+    )]
     BadState(String),
 
     #[error(
         "State {0} has no arg.\n\
-        Please specify an arg of true or false."
+        Please specify an arg of true, false, or * for both."
     )]
-    NoArg(u32),
+    NoArg(StateRef),
 
     #[error(
         "Could not parse the 'arg' for State {1}.\n\
-        Args must be either true or false, but I found {0:?}."
+        Args must be true, false, or * (or any) for both, but I found {0:?}."
     )]
-    BadArg(String, u32),
+    BadArg(String, StateRef),
 
     #[error(
         "Could not parse the 'jump' for State {1} with Arg {2}.\n\
-        Jumps must be non-negative integers, but I found {0:?}."
+        Jumps must be non-negative integers or labels, optionally with an offset like `loop+3`,\n\
+        or relative to the state, like `+1`, `-2` or `next`, but I found {0:?}."
     )]
-    BadJump(String, u32, bool),
+    BadJump(String, StateRef, ArgPattern),
 
     #[error(
         "Could not parse the 'read' for State {1} with Arg {2}.\n\
         Reads must be either true or false, but I found {0:?}."
     )]
-    BadRead(String, u32, bool),
+    BadRead(String, StateRef, ArgPattern),
 
-    #[error("Invalid command at State {0} with Arg {1} for Register {2}: {3}")]
-    BadCommand(u32, bool, usize, BadRegisterCmd),
+    #[error("Invalid command at State {0} with Arg {1} for {2}: {3}")]
+    BadCommand(StateRef, ArgPattern, String, BadRegisterCmd),
+
+    #[error(
+        "Invalid register {0:?} at State {1} with Arg {2}.\n\
+        Commands can only be sent to registers 0 to {3}, or to registers named in the header."
+    )]
+    BadRegister(String, StateRef, ArgPattern, usize),
+
+    #[error("More than one command was given to {2} at State {0} with Arg {1}.")]
+    RegisterConflict(StateRef, ArgPattern, String),
+
+    #[error(
+        "{2} reads before the end of its commands at State {0} with Arg {1}.\n\
+        A read decides where to go next, so it has to be the last command."
+    )]
+    EarlyRead(StateRef, ArgPattern, String),
 
     #[error(
         "Attempt to read from multiple sources at State {0} with Arg {1}.\n\
         There must only be at most 1 read per command."
     )]
-    MultiRead(u32, bool),
+    MultiRead(StateRef, ArgPattern),
 }
 
+/// A line of code, before its labels are turned into state numbers.
 pub struct RieLine {
-    pub state: u32,
+    pub state: StateRef,
     pub arg: bool,
-    pub cmd: TMCmd,
+    pub goto: Option<StateRef>,
+    pub read: Option<bool>,
+    pub register_cmds: Vec<RegisterCmd>,
 }
 
 impl RieLine {
-    pub fn to_string(state_digits: usize, state: u32, arg: bool, cmd: &TMCmd) -> String {
+    pub fn to_string(
+        state_digits: usize,
+        register_names: &RegisterNames,
+        state: u32,
+        arg: bool,
+        cmd: &TMCmd,
+    ) -> String {
         let mut instructions = vec![format!("Goto {:>state_digits$}", cmd.goto)];
 
         // which register this command reads from, if any
@@ -75,14 +145,14 @@ impl RieLine {
                 read_register_id = Some(i);
                 continue;
             }
-            instructions.push(format!("{cmd:?} Register {i}"));
+            instructions.push(format!("{cmd:?} {}", register_names.describe(i)));
         }
 
         // list which source to read from for the next command
         if let Some(read) = cmd
             .read
             .map(|x| x.to_string())
-            .or_else(|| read_register_id.map(|x| format!("Register {x}")))
+            .or_else(|| read_register_id.map(|x| register_names.describe(x)))
         {
             instructions.push(format!("Read {read}"));
         }
@@ -94,26 +164,66 @@ impl RieLine {
         )
     }
 
-    pub fn parse(line: &str, register_count: usize) -> Result<Self, RieLineErr> {
+    /// Parses a line of code into one line for every step it takes.
+    /// Cells with more than one command, like `>>>`, take more than one step,
+    /// so the steps after the first get states of their own, labelled `<state>#<arg>.<step>`.
+    /// A wildcard arg gives one line for each arg, which share the steps after the first.
+    /// Also returns how many of those extra step states there are.
+    pub fn parse(
+        line: &str,
+        register_names: &RegisterNames,
+    ) -> Result<(Vec<Self>, usize), RieLineErr> {
         use RieLineErr::*;
 
         let tokens = &mut get_tokens(line);
-        let state = next_token(tokens, BadState)?.ok_or(NoState)?;
-        let arg = next_token(tokens, |token| BadArg(token, state))?.ok_or(NoArg(state))?;
-        let goto = next_token(tokens, |token| BadJump(token, state, arg))?.unwrap_or(state);
-        let read = next_token(tokens, |token| BadRead(token, state, arg))?;
-
-        let mut register_cmds = vec![];
-        for (i, token) in (0..register_count).zip(tokens) {
-            register_cmds.push(
-                token
-                    .parse::<RegisterCmd>()
-                    .map_err(|e| BadCommand(state, arg, i, e))?,
-            );
+        let state: StateRef = next_token(tokens, BadState)?.ok_or(NoState)?;
+        if let StateRef::Offset(..) = state {
+            return Err(BadState(state.to_string()));
+        }
+        let arg: ArgPattern = next_token(tokens, |token| BadArg(token, state.clone()))?
+            .ok_or_else(|| NoArg(state.clone()))?;
+        let goto = next_token::<String, _>(tokens, |token| BadJump(token, state.clone(), arg))?
+            .map(|token| {
+                StateRef::parse_goto(&token, &state).map_err(|_| BadJump(token, state.clone(), arg))
+            })
+            .transpose()?;
+        let read = next_token(tokens, |token| BadRead(token, state.clone(), arg))?;
+
+        // commands go to the register of their column, unless they say otherwise with `@`
+        let register_count = register_names.0.len();
+        let mut sequences = vec![vec![]; register_count];
+        for (column, token) in (0..register_count).zip(tokens) {
+            let (token, register) = match token.split_once(RegisterCmd::ADDRESS_SEPARATOR) {
+                Some((token, target)) => {
+                    let register = register_names.find(target.trim()).ok_or_else(|| {
+                        BadRegister(target.to_owned(), state.clone(), arg, register_count - 1)
+                    })?;
+                    (token, register)
+                }
+                None => (token, column),
+            };
+            let cmds = RegisterCmd::parse_sequence(token.trim()).map_err(|e| {
+                BadCommand(state.clone(), arg, register_names.describe(register), e)
+            })?;
+            let Some((_last, before)) = cmds.split_last() else {
+                continue;
+            };
+            if before.contains(&RegisterCmd::Read) {
+                return Err(EarlyRead(state, arg, register_names.describe(register)));
+            }
+            if !sequences[register].is_empty() {
+                return Err(RegisterConflict(
+                    state,
+                    arg,
+                    register_names.describe(register),
+                ));
+            }
+            sequences[register] = cmds;
         }
 
-        let read_count = register_cmds
+        let read_count = sequences
             .iter()
+            .flatten()
             .filter(|&cmd| *cmd == RegisterCmd::Read)
             .count()
             + read.is_some() as usize;
@@ -122,12 +232,112 @@ impl RieLine {
             return Err(MultiRead(state, arg));
         }
 
-        let cmd = TMCmd {
-            goto,
+        let mut steps = RegisterCmd::steps(&sequences);
+        let Some(last) = steps.pop() else {
+            unreachable!("Every line takes at least one step.");
+        };
+        if steps.is_empty() {
+            let lines = arg
+                .args()
+                .into_iter()
+                .map(|arg| Self {
+                    state: state.clone(),
+                    arg,
+                    goto: goto.clone(),
+                    read,
+                    register_cmds: last.clone(),
+                })
+                .collect();
+            return Ok((lines, 0));
+        }
+
+        // every step but the last goes on to the next with arg false,
+        // so only the first step is different for each arg
+        let step_state = |step: usize| StateRef::Label(format!("{state}#{arg}.{step}"));
+        let last_step = steps.len();
+        let mut lines = vec![];
+        for (step, register_cmds) in steps.into_iter().enumerate() {
+            let next = Some(step_state(step + 1));
+            if step == 0 {
+                for arg in arg.args() {
+                    lines.push(Self {
+                        state: state.clone(),
+                        arg,
+                        goto: next.clone(),
+                        read: Some(false),
+                        register_cmds: register_cmds.clone(),
+                    });
+                }
+                continue;
+            }
+            lines.push(Self {
+                state: step_state(step),
+                arg: false,
+                goto: next,
+                read: Some(false),
+                register_cmds,
+            });
+        }
+        lines.push(Self {
+            state: step_state(last_step),
+            arg: false,
+            goto: goto.or(Some(state)),
             read,
-            register_cmds,
+            register_cmds: last,
+        });
+        Ok((lines, last_step))
+    }
+
+    /// Turns the line into a command, using `state` as the number of this line's state
+    /// and `resolve` to find the numbers of any other states.
+    pub fn to_cmd<E>(
+        &self,
+        state: u32,
+        resolve: impl Fn(&StateRef) -> Result<u32, E>,
+    ) -> Result<TMCmd, E> {
+        let goto = match &self.goto {
+            Some(goto) => resolve(goto)?,
+            None => state,
         };
+        Ok(TMCmd {
+            goto,
+            read: self.read,
+            register_cmds: self.register_cmds.clone(),
+        })
+    }
+}
 
-        Ok(Self { state, arg, cmd })
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::rie::RieProgram;
+
+    fn step_states(line: &str) -> usize {
+        let (_lines, step_states) = RieLine::parse(line, &RegisterNames::unnamed(2)).unwrap();
+        step_states
+    }
+
+    #[test]
+    fn counts_step_states() {
+        assert_eq!(step_states("\t0\tfalse\t1\t\t?"), 0);
+        assert_eq!(step_states("\t0\t*\t1\t\t?"), 0);
+        assert_eq!(step_states("\t0\tfalse\t1\t\t>>?"), 2);
+        assert_eq!(step_states("\t0\t*\t1\t\t>>?"), 2);
+        assert_eq!(step_states("\t0\t*\t1\t\t>>\t%?"), 1);
+    }
+
+    #[test]
+    fn reports_split_states() {
+        let split_states = |code: &str| {
+            let source = format!("\tstate\targ\tgoto\tread\treg\n{code}");
+            let (program, _warnings) = RieProgram::parse(&source).unwrap();
+            program.split_states()
+        };
+        assert_eq!(split_states("\t0\t*\t0\t\t?\n"), 0);
+        assert_eq!(split_states("\t0\t*\t0\t\t>>?\n"), 2);
+        assert_eq!(
+            split_states("\t0\ttrue\t0\t\t>?\n\t0\tfalse\t0\t\t>>>?\n"),
+            4
+        );
     }
 }
@@ -0,0 +1,53 @@
+use super::RieErr;
+use std::{
+    env,
+    path::{Path, PathBuf},
+};
+
+/// The directories that `.rie` files are looked up in.
+#[derive(Debug, Clone, Default)]
+pub struct SearchPath {
+    dirs: Vec<PathBuf>,
+}
+
+impl SearchPath {
+    pub const ENV_VAR: &'static str = "RIEASM_PATH";
+
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds a directory to search after every directory added so far.
+    pub fn push(&mut self, dir: impl Into<PathBuf>) {
+        self.dirs.push(dir.into());
+    }
+
+    /// Adds every directory listed in the `RIEASM_PATH` environment variable,
+    /// separated the same way as `PATH` is on this platform.
+    pub fn push_env(&mut self) {
+        if let Some(paths) = env::var_os(Self::ENV_VAR) {
+            self.dirs
+                .extend(env::split_paths(&paths).filter(|dir| !dir.as_os_str().is_empty()));
+        }
+    }
+
+    pub fn dirs(&self) -> &[PathBuf] {
+        &self.dirs
+    }
+
+    /// Finds a file, first relative to `relative_to` (or the current directory if there is none),
+    /// then in each directory of the search path in order.
+    pub fn resolve(&self, name: &str, relative_to: Option<&Path>) -> Result<PathBuf, RieErr> {
+        let first = relative_to.map_or_else(|| PathBuf::from(name), |dir| dir.join(name));
+        let candidates = [first]
+            .into_iter()
+            .chain(self.dirs.iter().map(|dir| dir.join(name)))
+            .collect::<Vec<_>>();
+
+        candidates
+            .iter()
+            .find(|path| path.is_file())
+            .cloned()
+            .ok_or_else(|| RieErr::FileNotFound(name.to_owned(), candidates))
+    }
+}
@@ -0,0 +1,47 @@
+use std::{
+    fmt::Display,
+    path::{Path, PathBuf},
+    sync::Arc,
+};
+
+/// Where a line of source code came from.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct SourcePos {
+    pub file: Arc<Path>,
+    pub line: usize,
+    /// Where the macro containing this line was used, if it came from a macro.
+    pub expanded_from: Option<Arc<SourcePos>>,
+}
+
+impl SourcePos {
+    pub fn new(file: &Arc<Path>, line: usize) -> Self {
+        Self {
+            file: Arc::clone(file),
+            line,
+            expanded_from: None,
+        }
+    }
+
+    /// Where the outermost macro containing this line was used, or this line if it isn't in a macro.
+    pub fn outermost(&self) -> &SourcePos {
+        match &self.expanded_from {
+            Some(use_pos) => use_pos.outermost(),
+            None => self,
+        }
+    }
+}
+
+impl Display for SourcePos {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}:{}", self.file.display(), self.line)?;
+        if let Some(use_pos) = &self.expanded_from {
+            write!(f, " (in a macro used at {use_pos})")?;
+        }
+        Ok(())
+    }
+}
+
+/// The name given to sources that don't come from a file.
+pub fn unnamed_source() -> Arc<Path> {
+    Arc::from(PathBuf::from("<source>"))
+}
@@ -0,0 +1,40 @@
+use super::{cell_position, RieProgram};
+
+impl RieProgram {
+    /// A tab separated table of every cell that [`RieProgram::rle`] writes,
+    /// with the line of source, state, arg, and field of the command it belongs to.
+    ///
+    /// `field` is `register`, `read` or `goto`, `register` is the number of the register for register fields,
+    /// and `index` is which bit of the field the cell is (goto bits are least significant first).
+    pub fn source_map(&self) -> String {
+        let columns = self.assemble();
+        let mut out = String::from("x\ty\tstate\targ\tfield\tregister\tindex\tbit\tsource\n");
+        for (address, column) in columns.iter().flatten().enumerate() {
+            let (state, arg) = (address / 2, address % 2 == 1);
+            let source = self
+                .source(state, arg)
+                .map_or_else(String::new, |pos| pos.to_string());
+            let mut row = 0;
+            for (segment, bits) in column.iter().enumerate() {
+                // registers come first, in reverse, then the read and the goto
+                let (field, register) = match segment.checked_sub(self.register_count()) {
+                    None => (
+                        "register",
+                        (self.register_count() - 1 - segment).to_string(),
+                    ),
+                    Some(0) => ("read", String::new()),
+                    Some(_) => ("goto", String::new()),
+                };
+                for (index, &bit) in bits.iter().enumerate() {
+                    let (x, y) = cell_position(address, row, segment);
+                    out.push_str(&format!(
+                        "{x}\t{y}\t{state}\t{arg}\t{field}\t{register}\t{index}\t{}\t{source}\n",
+                        bit as u8
+                    ));
+                    row += 1;
+                }
+            }
+        }
+        out
+    }
+}
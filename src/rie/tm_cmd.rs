@@ -1,7 +1,8 @@
 use super::register_cmd::RegisterCmd;
 use crate::helpers::extend_vec_to;
+use serde::{Deserialize, Serialize};
 
-#[derive(Debug, Clone, Default)]
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
 pub struct TMCmd {
     pub goto: u32,
     pub read: Option<bool>,
@@ -9,6 +10,16 @@ pub struct TMCmd {
 }
 
 impl TMCmd {
+    /// Whether this command halts without doing anything, like unspecified lines do.
+    pub fn is_halt(&self) -> bool {
+        self.goto == 0
+            && self.read.is_none()
+            && self
+                .register_cmds
+                .iter()
+                .all(|&cmd| cmd == RegisterCmd::Noop)
+    }
+
     pub fn assemble(&self, state_bit_count: u32, register_count: usize) -> Vec<Vec<bool>> {
         // assemble register commands
         let mut out = self
@@ -0,0 +1,385 @@
+use super::RieProgram;
+use std::fmt::Write;
+
+/// The name of the generated module.
+pub const MODULE_NAME: &str = "rie_machine";
+
+/// What the 4 bits of each register command are, with bit 0 being the top cell of the segment.
+const OPS: [(&str, &str); 5] = [
+    ("PUSH", "4'b0100"),
+    ("PULL", "4'b0010"),
+    ("FLIP", "4'b0111"),
+    ("FLIP_PULL", "4'b0011"),
+    ("READ", "4'b1001"),
+];
+
+impl RieProgram {
+    /// A synthesizable Verilog module of a Read-If/Else machine running the program,
+    /// for cross-checking it in an HDL simulator like Icarus Verilog or Verilator.
+    ///
+    /// The ROM holds the same words as [`RieProgram::rom_image`].
+    /// Registers are tapes of `2^HEAD_BITS` cells whose heads start in the middle and wrap around at the ends.
+    /// Each register also tracks the lowest and highest cells flipped so far, so they can be shown like [`Tape`](super::machine::Tape) does.
+    /// A testbench that prints the same report as `rieasm run` is included when `RIE_TESTBENCH` is defined.
+    pub fn to_verilog(&self) -> String {
+        let registers = self.register_count();
+        let state_bits = self.state_bits as usize;
+        let word_bits = self.word_bits();
+        // a program with a single state still needs a wire for it
+        let state_width = state_bits.max(1);
+        let addresses = 2 << state_bits;
+        let address = if state_bits == 0 {
+            "arg".to_owned()
+        } else {
+            "{state, arg}".to_owned()
+        };
+        let read_false = 4 * registers;
+        let read_true = read_false + 1;
+
+        let mut out = String::new();
+        let o = &mut out;
+        writeln!(o, "// generated by rieasm").unwrap();
+        writeln!(
+            o,
+            "// A Read-If/Else machine with {} state bits and {} registers, running the program.",
+            state_bits, registers
+        )
+        .unwrap();
+        writeln!(
+            o,
+            "// Holding rst high for a clock starts the machine at state 0 with arg false."
+        )
+        .unwrap();
+        writeln!(
+            o,
+            "// It then runs one command per clock, until a command without a read halts it."
+        )
+        .unwrap();
+        writeln!(o, "// Each register is a tape of 2^HEAD_BITS cells, and its head wraps around at the ends.").unwrap();
+        writeln!(
+            o,
+            "// low and high are the lowest and highest cells of a register flipped so far."
+        )
+        .unwrap();
+        writeln!(o, "module {MODULE_NAME} #(").unwrap();
+        writeln!(o, "    parameter HEAD_BITS = 8").unwrap();
+        writeln!(o, ") (").unwrap();
+        writeln!(o, "    input wire clk,").unwrap();
+        writeln!(o, "    input wire rst,").unwrap();
+        for register in 0..registers {
+            let name = self.register_names.describe(register);
+            writeln!(o, "    // {name}").unwrap();
+            writeln!(o, "    output reg [(1 << HEAD_BITS) - 1:0] tape{register},").unwrap();
+            writeln!(o, "    output reg [HEAD_BITS - 1:0] head{register},").unwrap();
+            writeln!(o, "    output reg [HEAD_BITS - 1:0] low{register},").unwrap();
+            writeln!(o, "    output reg [HEAD_BITS - 1:0] high{register},").unwrap();
+        }
+        writeln!(o, "    output reg [{}:0] state,", state_width - 1).unwrap();
+        writeln!(o, "    output reg arg,").unwrap();
+        writeln!(o, "    output reg halted,").unwrap();
+        writeln!(o, "    output reg [31:0] steps").unwrap();
+        writeln!(o, ");").unwrap();
+        for (name, bits) in OPS {
+            writeln!(o, "    localparam {name} = {bits};").unwrap();
+        }
+        writeln!(o).unwrap();
+
+        // the rom
+        writeln!(
+            o,
+            "    // one word for every address, bit 0 being the top cell of the column"
+        )
+        .unwrap();
+        writeln!(
+            o,
+            "    reg [{}:0] rom [0:{}];",
+            word_bits - 1,
+            addresses - 1
+        )
+        .unwrap();
+        writeln!(o, "    initial begin").unwrap();
+        for (i, column) in self.assemble().iter().flatten().enumerate() {
+            let bits = column
+                .iter()
+                .flatten()
+                .rev()
+                .map(|&bit| if bit { '1' } else { '0' })
+                .collect::<String>();
+            writeln!(o, "        rom[{i}] = {word_bits}'b{bits};").unwrap();
+        }
+        writeln!(o, "    end").unwrap();
+        writeln!(o, "    wire [{}:0] word = rom[{address}];", word_bits - 1).unwrap();
+        writeln!(o).unwrap();
+
+        // decoding the word
+        let mut reads = vec![format!("word[{read_false}]"), format!("word[{read_true}]")];
+        let mut next_arg = vec![format!("word[{read_true}]")];
+        for register in 0..registers {
+            let low = 4 * (registers - 1 - register);
+            writeln!(o, "    wire [3:0] op{register} = word[{}:{low}];", low + 3).unwrap();
+            reads.push(format!("op{register} == READ"));
+            next_arg.push(format!(
+                "(op{register} == READ && tape{register}[head{register}])"
+            ));
+        }
+        if state_bits == 0 {
+            writeln!(o, "    wire [0:0] goto = 1'b0;").unwrap();
+        } else {
+            writeln!(
+                o,
+                "    wire [{}:0] goto = word[{}:{}];",
+                state_bits - 1,
+                read_true + state_bits,
+                read_true + 1
+            )
+            .unwrap();
+        }
+        writeln!(o, "    wire reads = {};", reads.join(" || ")).unwrap();
+        writeln!(o, "    wire next_arg = {};", next_arg.join(" || ")).unwrap();
+        writeln!(o).unwrap();
+
+        // running a step
+        writeln!(o, "    always @(posedge clk) begin").unwrap();
+        writeln!(o, "        if (rst) begin").unwrap();
+        for register in 0..registers {
+            writeln!(o, "            tape{register} <= 0;").unwrap();
+            writeln!(o, "            head{register} <= 1 << (HEAD_BITS - 1);").unwrap();
+            // nothing flipped yet, so high is just below the middle
+            writeln!(o, "            low{register} <= 1 << (HEAD_BITS - 1);").unwrap();
+            writeln!(
+                o,
+                "            high{register} <= (1 << (HEAD_BITS - 1)) - 1;"
+            )
+            .unwrap();
+        }
+        writeln!(o, "            state <= 0;").unwrap();
+        writeln!(o, "            arg <= 0;").unwrap();
+        writeln!(o, "            halted <= 0;").unwrap();
+        writeln!(o, "            steps <= 0;").unwrap();
+        writeln!(o, "        end else if (!halted) begin").unwrap();
+        for register in 0..registers {
+            let (tape, head) = (format!("tape{register}"), format!("head{register}"));
+            let (low, high) = (format!("low{register}"), format!("high{register}"));
+            writeln!(o, "            case (op{register})").unwrap();
+            writeln!(o, "                PUSH: {head} <= {head} + 1;").unwrap();
+            writeln!(o, "                PULL: {head} <= {head} - 1;").unwrap();
+            writeln!(o, "                default: ;").unwrap();
+            writeln!(o, "            endcase").unwrap();
+            writeln!(
+                o,
+                "            if (op{register} == FLIP || op{register} == FLIP_PULL) begin"
+            )
+            .unwrap();
+            writeln!(o, "                {tape}[{head}] <= !{tape}[{head}];").unwrap();
+            writeln!(o, "                if ({head} < {low}) {low} <= {head};").unwrap();
+            writeln!(o, "                if ({head} > {high}) {high} <= {head};").unwrap();
+            writeln!(
+                o,
+                "                if (op{register} == FLIP_PULL) {head} <= {head} - 1;"
+            )
+            .unwrap();
+            writeln!(o, "            end").unwrap();
+        }
+        writeln!(o, "            state <= goto;").unwrap();
+        writeln!(o, "            if (reads) arg <= next_arg;").unwrap();
+        writeln!(o, "            else halted <= 1;").unwrap();
+        writeln!(o, "            steps <= steps + 1;").unwrap();
+        writeln!(o, "        end").unwrap();
+        writeln!(o, "    end").unwrap();
+        writeln!(o, "endmodule").unwrap();
+        writeln!(o).unwrap();
+
+        // the testbench
+        writeln!(o, "`ifdef RIE_TESTBENCH").unwrap();
+        writeln!(o, "// Runs the machine until it halts or MAX_STEPS commands have run, then prints the same report as `rieasm run`:").unwrap();
+        writeln!(o, "// every register from its lowest flipped cell to its highest, widened to reach cell 0 and the head, which is in brackets.").unwrap();
+        writeln!(o, "module {MODULE_NAME}_tb;").unwrap();
+        writeln!(o, "    parameter HEAD_BITS = 8;").unwrap();
+        writeln!(o, "    parameter MAX_STEPS = 1000000;").unwrap();
+        writeln!(o, "    reg clk = 0;").unwrap();
+        writeln!(o, "    reg rst = 1;").unwrap();
+        for register in 0..registers {
+            writeln!(o, "    wire [(1 << HEAD_BITS) - 1:0] tape{register};").unwrap();
+            writeln!(o, "    wire [HEAD_BITS - 1:0] head{register};").unwrap();
+            writeln!(o, "    wire [HEAD_BITS - 1:0] low{register};").unwrap();
+            writeln!(o, "    wire [HEAD_BITS - 1:0] high{register};").unwrap();
+        }
+        writeln!(o, "    wire [{}:0] state;", state_width - 1).unwrap();
+        writeln!(o, "    wire arg;").unwrap();
+        writeln!(o, "    wire halted;").unwrap();
+        writeln!(o, "    wire [31:0] steps;").unwrap();
+        let ports = (0..registers)
+            .flat_map(|register| {
+                ["tape", "head", "low", "high"]
+                    .map(|port| format!(".{port}{register}({port}{register})"))
+            })
+            .chain(
+                ["clk", "rst", "state", "arg", "halted", "steps"]
+                    .map(|port| format!(".{port}({port})")),
+            )
+            .collect::<Vec<_>>();
+        writeln!(
+            o,
+            "    {MODULE_NAME} #(.HEAD_BITS(HEAD_BITS)) machine ({});",
+            ports.join(", ")
+        )
+        .unwrap();
+        writeln!(o).unwrap();
+        writeln!(o, "    task show_tape(input [(1 << HEAD_BITS) - 1:0] tape, input [HEAD_BITS - 1:0] head, input [HEAD_BITS - 1:0] low, input [HEAD_BITS - 1:0] high);").unwrap();
+        writeln!(o, "        integer i, first, last;").unwrap();
+        writeln!(o, "        begin").unwrap();
+        writeln!(o, "            first = low < head ? low : head;").unwrap();
+        writeln!(o, "            last = high > head ? high : head;").unwrap();
+        writeln!(o, "            for (i = first; i <= last; i = i + 1) begin").unwrap();
+        writeln!(
+            o,
+            "                if (i == head) $write(\"[%0d]\", tape[i]);"
+        )
+        .unwrap();
+        writeln!(o, "                else $write(\"%0d\", tape[i]);").unwrap();
+        writeln!(o, "            end").unwrap();
+        writeln!(o, "            $write(\"\\n\");").unwrap();
+        writeln!(o, "        end").unwrap();
+        writeln!(o, "    endtask").unwrap();
+        writeln!(o).unwrap();
+        writeln!(o, "    always #1 clk = !clk;").unwrap();
+        writeln!(o, "    initial begin").unwrap();
+        writeln!(o, "        @(posedge clk);").unwrap();
+        writeln!(o, "        #1 rst = 0;").unwrap();
+        writeln!(o, "        wait (halted || steps >= MAX_STEPS);").unwrap();
+        writeln!(o, "        if (halted) $display(\"Halted after %0d steps, at state %0d.\", steps, state);").unwrap();
+        writeln!(
+            o,
+            "        else if (arg) $display(\"Still running after %0d steps, at state %0d with arg true.\", steps, state);"
+        )
+        .unwrap();
+        writeln!(
+            o,
+            "        else $display(\"Still running after %0d steps, at state %0d with arg false.\", steps, state);"
+        )
+        .unwrap();
+        for register in 0..registers {
+            let name = self.register_names.describe(register);
+            writeln!(o, "        $write(\"    {name}: \");").unwrap();
+            writeln!(
+                o,
+                "        show_tape(tape{register}, head{register}, low{register}, high{register});"
+            )
+            .unwrap();
+        }
+        writeln!(o, "        $finish;").unwrap();
+        writeln!(o, "    end").unwrap();
+        writeln!(o, "endmodule").unwrap();
+        writeln!(o, "`endif").unwrap();
+        out
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::rie::register_cmd::RegisterCmd;
+
+    const SOURCE: &str = "\
+\tstate\targ\tgoto\tread\treg:x\treg:y
+\t0\tfalse\t1\ttrue\t>\t%
+\t1\tfalse\t2\t\t<\t%<
+\t1\ttrue\t3\t\t\t?
+\t2\tfalse\t0\tfalse
+\t3\ttrue\t0\t\t?
+";
+
+    /// The words of the ROM's `initial` block, which must set every address in order.
+    fn rom_words(verilog: &str) -> Vec<Vec<bool>> {
+        verilog
+            .lines()
+            .filter_map(|line| line.trim().strip_prefix("rom["))
+            .enumerate()
+            .map(|(i, line)| {
+                let (address, word) = line.split_once("] = ").unwrap();
+                assert_eq!(address.parse::<usize>().unwrap(), i);
+                let (_width, bits) = word.trim_end_matches(';').split_once("'b").unwrap();
+                // bit 0 is written last
+                bits.chars().rev().map(|bit| bit == '1').collect()
+            })
+            .collect()
+    }
+
+    /// The bits of `word` that a wire like `wire [3:0] op0 = word[11:8];` is given.
+    fn field(verilog: &str, wire: &str) -> std::ops::RangeInclusive<usize> {
+        let line = verilog
+            .lines()
+            .find(|line| line.contains(&format!("] {wire} = word[")))
+            .unwrap();
+        let (_decl, bits) = line.split_once("word[").unwrap();
+        let (high, low) = bits.trim_end_matches("];").split_once(':').unwrap();
+        low.parse().unwrap()..=high.parse().unwrap()
+    }
+
+    fn op_bits(cmd: RegisterCmd) -> [bool; 4] {
+        use RegisterCmd::*;
+        let name = match cmd {
+            Noop => return [false; 4],
+            Push => "PUSH",
+            Pull => "PULL",
+            Flip => "FLIP",
+            FlipPull => "FLIP_PULL",
+            Read => "READ",
+        };
+        let (_name, bits) = OPS.iter().find(|(op, _bits)| *op == name).unwrap();
+        let mut out = [false; 4];
+        for (i, bit) in bits["4'b".len()..].chars().rev().enumerate() {
+            out[i] = bit == '1';
+        }
+        out
+    }
+
+    #[test]
+    fn rom_matches_assemble() {
+        let (program, _warnings) = RieProgram::parse(include_str!("../../program.rie")).unwrap();
+        let words = rom_words(&program.to_verilog());
+        assert_eq!(words.len(), 2 << program.state_bits());
+        for (word, column) in words.iter().zip(program.assemble().iter().flatten()) {
+            assert_eq!(word, &column.concat());
+        }
+    }
+
+    #[test]
+    fn ops_match_register_cmds() {
+        for (_token, cmd) in RegisterCmd::VALID_COMMANDS {
+            assert_eq!(op_bits(cmd), cmd.assemble());
+        }
+    }
+
+    #[test]
+    fn decodes_every_field() {
+        let (program, _warnings) = RieProgram::parse(SOURCE).unwrap();
+        let verilog = program.to_verilog();
+        let words = rom_words(&verilog);
+        let goto = field(&verilog, "goto");
+        let ops = (0..program.register_count())
+            .map(|register| field(&verilog, &format!("op{register}")))
+            .collect::<Vec<_>>();
+        let read_false = 4 * program.register_count();
+        assert!(verilog.contains(&format!(
+            "wire reads = word[{read_false}] || word[{}]",
+            read_false + 1
+        )));
+
+        for (word, cmd) in words.iter().zip(program.commands.iter().flatten()) {
+            let goto_value = word[goto.clone()]
+                .iter()
+                .rev()
+                .fold(0, |value, &bit| value << 1 | bit as u32);
+            assert_eq!(goto_value, cmd.goto);
+            assert_eq!(
+                (word[read_false], word[read_false + 1]),
+                (cmd.read == Some(false), cmd.read == Some(true))
+            );
+            for (register, op) in ops.iter().enumerate() {
+                let register_cmd = cmd.register_cmds.get(register).copied().unwrap_or_default();
+                assert_eq!(word[op.clone()], op_bits(register_cmd));
+            }
+        }
+    }
+}
@@ -0,0 +1,42 @@
+use std::io::{stderr, stdin, stdout, IsTerminal, Write};
+
+pub const RED: &str = "\x1b[0;31;1m";
+pub const YELLOW: &str = "\x1b[0;33;1m";
+pub const GREEN: &str = "\x1b[0;32;1m";
+pub const RESET: &str = "\x1b[0m";
+
+pub fn print_flush(message: &str) {
+    eprint!("{message}");
+    stdout().flush().expect("Couldn't flush stdout.");
+}
+
+pub fn get_line() -> String {
+    let mut input = String::new();
+    stdin()
+        .read_line(&mut input)
+        .expect("Couldn't read line from stdin.");
+    input
+}
+
+/// Whether someone is at a terminal to answer prompts,
+/// rather than the program running in a script or with piped input.
+pub fn is_interactive() -> bool {
+    stdin().is_terminal() && stderr().is_terminal()
+}
+
+pub fn pause() {
+    print_flush("[Enter]");
+    get_line();
+}
+
+pub fn ask_y_n() -> bool {
+    loop {
+        print_flush(&format!("[{GREEN}Y{RESET}/{RED}N{RESET}]: "));
+        match get_line().trim().to_uppercase().chars().next() {
+            Some('Y') => return true,
+            Some('N') => return false,
+            None => return false,
+            _ => {}
+        }
+    }
+}